@@ -1,9 +1,13 @@
+mod multipart;
+
 use std::collections::BTreeMap;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::string::ToString;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fs, thread};
 
 use anyhow::{bail, Result};
@@ -11,13 +15,15 @@ use crossbeam_channel::Sender;
 use log::{error, info};
 use once_cell::sync::Lazy;
 use tempdir::TempDir;
-use tiny_http::{Method, Request, Response};
+use tiny_http::{Header, Method, Request, Response};
 use tinytemplate::TinyTemplate;
-use zip::ZipArchive;
 
-use cs453_pap_worker::packet::{Packet, Registry, Status};
-use cs453_pap_worker::process::analyze;
-use cs453_pap_worker::util_docker::Dock;
+use cs453_pap_worker::assignment::Assignment;
+use cs453_pap_worker::cores::CorePool;
+use cs453_pap_worker::gradebook::{self, Format, Selection};
+use cs453_pap_worker::packet::{check_submitter, Packet, Registry, Status};
+use cs453_pap_worker::util_archive::{extract, pack_zip};
+use cs453_pap_worker::{pool, retention};
 
 /// Absolute path to the `data` directory
 static REGISTRY: Lazy<Registry> = Lazy::new(|| {
@@ -30,6 +36,17 @@ static REGISTRY: Lazy<Registry> = Lazy::new(|| {
     Registry::new(path).unwrap_or_else(|e| panic!("unable to initialize the registry: {}", e))
 });
 
+/// Token granting access to the staff views, taken from the `PAP_ADMIN_TOKEN` environment
+/// variable (staff views are disabled if it is not set)
+static ADMIN_TOKEN: Lazy<Option<String>> = Lazy::new(|| {
+    std::env::var("PAP_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+});
+
+/// CPU cores shared by fuzzing campaigns
+static CORES: Lazy<CorePool> = Lazy::new(CorePool::detect);
+
 /// The welcome message
 static WELCOME: Lazy<String> = Lazy::new(|| {
     let mut t = TinyTemplate::new();
@@ -41,6 +58,16 @@ static WELCOME: Lazy<String> = Lazy::new(|| {
     t.render("index", &c).expect("template")
 });
 
+/// The welcome page with an upload form, for browsers
+static WELCOME_HTML: Lazy<String> = Lazy::new(|| {
+    let mut t = TinyTemplate::new();
+    t.add_template("index", include_str!("../asset/index.html"))
+        .expect("template");
+    let mut c = BTreeMap::new();
+    c.insert("welcome", WELCOME.to_string());
+    t.render("index", &c).expect("template")
+});
+
 /// Hostname for the server
 const HOST: &str = "ugster72d.student.cs.uwaterloo.ca";
 
@@ -53,6 +80,24 @@ const NUMBER_OF_SERVERS: usize = 2;
 /// Number of worker instances
 const NUMBER_OF_WORKERS: usize = 8;
 
+/// Disk quota for all packets in the registry
+const DISK_QUOTA: u64 = retention::DEFAULT_DISK_QUOTA;
+
+/// Interval between two rounds of garbage collection
+const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum time a long-polling request on /progress is held before replying
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of long-polling requests on /progress held at the same time
+const MAX_LONG_POLLS: usize = 64;
+
+/// Number of long-polling requests on /progress currently held
+static LONG_POLLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximum size of a POST body
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
 /// Produce an error response related to user making a bad request
 fn make_sanity_error<S: AsRef<str>>(reason: S) -> Response<Cursor<Vec<u8>>> {
     Response::from_string(format!("[error] {}", reason.as_ref())).with_status_code(400)
@@ -63,16 +108,139 @@ fn make_server_error<S: AsRef<str>>(reason: S) -> Response<Cursor<Vec<u8>>> {
     Response::from_string(format!("[internal error] {}", reason.as_ref())).with_status_code(500)
 }
 
+/// Produce an error response when the server cannot take more of a kind of request
+fn make_busy_error<S: AsRef<str>>(reason: S) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Retry-After", "5").expect("header");
+    Response::from_string(format!("[busy] {}", reason.as_ref()))
+        .with_status_code(503)
+        .with_header(header)
+}
+
+/// A slot for a long-polling request, released when dropped
+struct LongPollSlot;
+
+impl LongPollSlot {
+    /// Take a slot, `None` if all of them are taken
+    fn acquire() -> Option<Self> {
+        LONG_POLLS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < MAX_LONG_POLLS).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Self)
+    }
+}
+
+impl Drop for LongPollSlot {
+    fn drop(&mut self) {
+        LONG_POLLS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 /// Produce a normal reply
 fn make_ok<S: AsRef<str>>(reason: S) -> Response<Cursor<Vec<u8>>> {
     Response::from_string(format!("{}\n", reason.as_ref())).with_status_code(200)
 }
 
+/// Produce a normal reply as an HTML page
+fn make_ok_html<S: AsRef<str>>(page: S) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "text/html; charset=utf-8").expect("header");
+    Response::from_string(page.as_ref()).with_header(header)
+}
+
+/// Produce a normal reply as a JSON document
+fn make_ok_json<S: AsRef<str>>(doc: S) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").expect("header");
+    Response::from_string(format!("{}\n", doc.as_ref())).with_header(header)
+}
+
+/// Produce a normal reply as a CSV file
+fn make_ok_csv(name: &str, csv: String) -> Response<Cursor<Vec<u8>>> {
+    make_ok_attachment(name, "text/csv", csv.into_bytes())
+}
+
+/// Make a response carrying a file to download
+fn make_ok_attachment(name: &str, mime: &str, data: Vec<u8>) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes("Content-Type", mime).expect("header");
+    let disposition = Header::from_bytes(
+        "Content-Disposition",
+        format!("attachment; filename=\"{}\"", name),
+    )
+    .expect("header");
+    Response::from_data(data)
+        .with_header(content_type)
+        .with_header(disposition)
+}
+
+/// Look up the value of a request header
+fn get_header<'a>(req: &'a Request, name: &'static str) -> Option<&'a str> {
+    req.headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+/// Check whether a request is made by the staff, i.e., carries the admin token as a bearer token
+///
+/// A request without credentials is not from the staff, while one with wrong credentials is
+/// rejected.
+fn check_staff(req: &Request) -> Result<bool> {
+    let credentials = match get_header(req, "Authorization") {
+        None => return Ok(false),
+        Some(value) => value,
+    };
+    let token = match ADMIN_TOKEN.as_ref() {
+        None => bail!("staff access is not enabled on this server"),
+        Some(token) => token,
+    };
+    match credentials.strip_prefix("Bearer ") {
+        Some(given)
+            if given.len() == token.len()
+                && given
+                    .bytes()
+                    .zip(token.bytes())
+                    .fold(0, |acc, (a, b)| acc | (a ^ b))
+                    == 0 =>
+        {
+            Ok(true)
+        }
+        _ => bail!("invalid credentials"),
+    }
+}
+
+/// Look up the value of a parameter in a URL query string
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .find_map(|(k, v)| (k == key).then_some(v))
+}
+
 /// Actions
 enum Action {
-    Default,
-    Submit(Vec<u8>),
-    Status(String),
+    Default {
+        html: bool,
+    },
+    Submit {
+        body: Vec<u8>,
+        submitter: Option<String>,
+        assignment: Option<String>,
+    },
+    Status {
+        hash: String,
+        staff: bool,
+    },
+    Plot(String),
+    Hangs(String),
+    Gradebook {
+        format: Format,
+        selection: Selection,
+        assignment: Option<String>,
+    },
+    Progress {
+        hash: String,
+        since: u64,
+    },
 }
 
 impl Action {
@@ -81,30 +249,142 @@ impl Action {
         let action = match req.method() {
             Method::Post => {
                 // parse command
-                if req.url() != "/submit" {
-                    bail!("invalid URL");
-                }
+                let (mut submitter, mut assignment) = match req.url().split_once('?') {
+                    None if req.url() == "/submit" => (None, None),
+                    Some(("/submit", query)) => (
+                        query_param(query, "submitter").map(|v| v.to_string()),
+                        query_param(query, "assignment").map(|v| v.to_string()),
+                    ),
+                    _ => bail!("invalid URL"),
+                };
                 // parse body
+                if req.body_length().is_some_and(|len| len > MAX_BODY_SIZE) {
+                    bail!("POST body is too large");
+                }
                 let mut body = vec![];
-                match req.as_reader().read_to_end(&mut body) {
+                match req
+                    .as_reader()
+                    .take(MAX_BODY_SIZE as u64 + 1)
+                    .read_to_end(&mut body)
+                {
                     Ok(_) => (),
                     Err(err) => {
                         bail!("unable to read POST body: {}", err);
                     }
                 }
-                Action::Submit(body)
+                if body.len() > MAX_BODY_SIZE {
+                    bail!("POST body is too large");
+                }
+
+                // unpack form uploads
+                let body = match get_header(req, "Content-Type").and_then(multipart::boundary) {
+                    None => body,
+                    Some(boundary) => {
+                        let mut package = None;
+                        for part in multipart::parse(&body, &boundary)? {
+                            let field = match part.name.as_deref() {
+                                Some("submitter") => Some(&mut submitter),
+                                Some("assignment") => Some(&mut assignment),
+                                _ => None,
+                            };
+                            if let Some(field) = field {
+                                let value = String::from_utf8(part.data)?;
+                                let value = value.trim();
+                                if !value.is_empty() {
+                                    *field = Some(value.to_string());
+                                }
+                            } else if package.is_none()
+                                && (part.name.as_deref() == Some("package")
+                                    || part.filename.is_some())
+                            {
+                                package = Some(part.data);
+                            }
+                        }
+                        match package {
+                            None => bail!("no file found in the uploaded form"),
+                            Some(data) => data,
+                        }
+                    }
+                };
+                if let Some(label) = submitter.as_ref() {
+                    check_submitter(label)?;
+                }
+                if let Some(name) = assignment.as_ref() {
+                    Assignment::check_name(name)?;
+                }
+                Action::Submit {
+                    body,
+                    submitter,
+                    assignment,
+                }
             }
             Method::Get => {
                 // parse command
                 let url = req.url();
                 if url.len() <= 1 {
-                    Action::Default
+                    let html = get_header(req, "Accept").is_some_and(|v| v.contains("text/html"));
+                    Action::Default { html }
                 } else {
-                    match url.strip_prefix("/status/") {
-                        None => {
+                    if let Some(name) = url.strip_prefix("/plot/") {
+                        let hash = name.strip_suffix(".csv").unwrap_or(name);
+                        return Ok(Action::Plot(hash.to_string()));
+                    }
+                    if let Some(rest) = url.strip_prefix("/gradebook") {
+                        let (name, query) = rest.split_once('?').unwrap_or((rest, ""));
+                        let format = match name {
+                            "" | ".csv" => Format::Csv,
+                            ".json" => Format::Json,
+                            _ => bail!("invalid URL"),
+                        };
+                        if !check_staff(req)? {
+                            bail!("the gradebook is only available to the staff");
+                        }
+                        let selection = match query_param(query, "latest") {
+                            None | Some("0") => Selection::All,
+                            Some("1") => Selection::LatestPerStudent,
+                            Some(v) => bail!("invalid value for latest: {}", v),
+                        };
+                        let assignment = query_param(query, "assignment").map(|v| v.to_string());
+                        if let Some(name) = assignment.as_ref() {
+                            Assignment::check_name(name)?;
+                        }
+                        return Ok(Action::Gradebook {
+                            format,
+                            selection,
+                            assignment,
+                        });
+                    }
+                    if let Some(name) = url.strip_prefix("/hangs/") {
+                        let hash = name.strip_suffix(".zip").unwrap_or(name);
+                        return Ok(Action::Hangs(hash.to_string()));
+                    }
+                    match (url.strip_prefix("/status/"), url.strip_prefix("/progress/")) {
+                        (Some(hash), _) => Action::Status {
+                            hash: hash.to_string(),
+                            staff: check_staff(req)?,
+                        },
+                        (None, Some(rest)) => {
+                            let (hash, since) = match rest.split_once('?') {
+                                None => (rest, 0),
+                                Some((hash, query)) => {
+                                    let since = match query_param(query, "since") {
+                                        None => 0,
+                                        Some(v) => match v.parse() {
+                                            Ok(n) => n,
+                                            Err(_) => bail!("invalid revision: {}", v),
+                                        },
+                                    };
+                                    (hash, since)
+                                }
+                            };
+                            Action::Progress {
+                                hash: hash.to_string(),
+                                since,
+                            }
+                        }
+                        (None, None) => {
                             bail!("invalid URL");
                         }
-                        Some(hash) => Action::Status(hash.to_string()),
                     }
                 }
             }
@@ -117,31 +397,81 @@ impl Action {
 }
 
 /// Entrypoint for /status
-fn handle_status(hash: String) -> Response<Cursor<Vec<u8>>> {
-    info!("processing request /status/{}", hash);
-    match REGISTRY.load_packet_status(hash) {
+fn handle_status(hash: String, staff: bool) -> Response<Cursor<Vec<u8>>> {
+    info!(
+        "processing request /status/{}{}",
+        hash,
+        if staff { " (staff)" } else { "" }
+    );
+    match REGISTRY.load_packet_status(hash, staff) {
         Ok(None) => make_ok("no such package"),
         Ok(Some(message)) => make_ok(message),
         Err(err) => make_server_error(err.to_string()),
     }
 }
 
+/// Entrypoint for /plot
+fn handle_plot(hash: String) -> Response<Cursor<Vec<u8>>> {
+    info!("processing request /plot/{}", hash);
+    match REGISTRY.load_result(hash.clone()) {
+        Ok(None) => make_ok("no such package or analysis not completed yet"),
+        Ok(Some(result)) => match result.aflpp_series_csv() {
+            None => make_ok("no AFL++ campaign metrics available for this package"),
+            Some(csv) => make_ok_csv(&format!("{}.csv", hash), csv),
+        },
+        Err(err) => make_server_error(err.to_string()),
+    }
+}
+
+/// Entrypoint for /hangs
+fn handle_hangs(hash: String) -> Response<Cursor<Vec<u8>>> {
+    info!("processing request /hangs/{}", hash);
+    match REGISTRY.load_hangs(hash.clone()) {
+        Ok(None) => make_ok("no such package or analysis not completed yet"),
+        Ok(Some(hangs)) if hangs.is_empty() => make_ok("no hanging inputs for this package"),
+        Ok(Some(hangs)) => match pack_zip(&hangs) {
+            Ok(data) => make_ok_attachment(&format!("{}-hangs.zip", hash), "application/zip", data),
+            Err(err) => make_server_error(err.to_string()),
+        },
+        Err(err) => make_server_error(err.to_string()),
+    }
+}
+
+/// Entrypoint for /gradebook
+fn handle_gradebook(
+    format: Format,
+    selection: Selection,
+    assignment: Option<String>,
+) -> Response<Cursor<Vec<u8>>> {
+    info!("processing request /gradebook");
+    let rendered = gradebook::collect(&REGISTRY, selection, assignment.as_deref())
+        .and_then(|entries| gradebook::render(&entries, format));
+    match (rendered, format) {
+        (Ok(csv), Format::Csv) => make_ok_csv("gradebook.csv", csv),
+        (Ok(doc), Format::Json) => make_ok_json(doc),
+        (Err(err), _) => make_server_error(err.to_string()),
+    }
+}
+
+/// Entrypoint for /progress (long-polling)
+fn handle_progress(hash: String, since: u64) -> Response<Cursor<Vec<u8>>> {
+    info!("processing request /progress/{}?since={}", hash, since);
+    match REGISTRY.watch_progress(hash, since, LONG_POLL_TIMEOUT) {
+        Ok(None) => make_ok("no such package").with_status_code(404),
+        Ok(Some(doc)) => make_ok_json(doc),
+        Err(err) => make_server_error(err.to_string()),
+    }
+}
+
 /// Entrypoint for /submit
-fn handle_submit(body: Vec<u8>, channel: &Sender<Packet>) -> Response<Cursor<Vec<u8>>> {
+fn handle_submit(
+    body: Vec<u8>,
+    submitter: Option<String>,
+    assignment: Option<String>,
+    channel: &Sender<Packet>,
+) -> Response<Cursor<Vec<u8>>> {
     info!("processing request /submit");
 
-    // construct zip archive
-    let mut reader = Cursor::new(body);
-    let mut zip = match ZipArchive::new(&mut reader) {
-        Ok(ar) => ar,
-        Err(err) => {
-            return make_sanity_error(format!(
-                "unable to parse POST body into a ZIP archive: {}",
-                err
-            ));
-        }
-    };
-
     // process the packet
     let dir = match TempDir::new("pap") {
         Ok(d) => d,
@@ -149,18 +479,19 @@ fn handle_submit(body: Vec<u8>, channel: &Sender<Packet>) -> Response<Cursor<Vec
             return make_server_error(format!("unable to create temporary directory: {}", err));
         }
     };
-    match zip.extract(dir.path()) {
+
+    // extract the archive with validation
+    match extract(&body, dir.path()) {
         Ok(_) => (),
         Err(err) => {
-            return make_server_error(format!(
-                "unable to extract the ZIP archive into the temporary directory: {}",
-                err
-            ));
+            info!("invalid archive: {}", err);
+            return make_sanity_error(format!("unable to extract the package archive: {}", err));
         }
     }
 
     // act on the request
-    let response = match REGISTRY.register(dir.path()) {
+    let response = match REGISTRY.register(dir.path(), submitter.as_deref(), assignment.as_deref())
+    {
         Ok((packet, existed)) => {
             // prepare the message first
             let head = if existed {
@@ -177,9 +508,11 @@ fn handle_submit(body: Vec<u8>, channel: &Sender<Packet>) -> Response<Cursor<Vec
             );
             info!("packet {}: {}", head, packet.id());
 
-            // send the packet to channel if this is a new package
+            // send the packet to channel if this is a new package (or its result is stale)
             if !existed {
-                REGISTRY.queue(packet.clone());
+                if let Err(err) = REGISTRY.queue(packet.clone()) {
+                    return make_server_error(format!("failed to queue the packet: {}", err));
+                }
                 match channel.send(packet) {
                     Ok(_) => make_ok(msg),
                     Err(err) => make_server_error(format!("failed to schedule analysis: {}", err)),
@@ -210,6 +543,7 @@ fn main() {
     // setup logging
     stderrlog::new()
         .module(module_path!())
+        .module("cs453_pap_worker")
         .timestamp(stderrlog::Timestamp::Second)
         .verbosity(stderrlog::LogLevelNum::Info)
         .init()
@@ -220,10 +554,10 @@ fn main() {
 
     // initialize the registry
     let mut count = 0;
-    for (packet, status) in REGISTRY.snapshot() {
+    for (packet, status) in REGISTRY.snapshot().expect("registry snapshot") {
         if matches!(status, Status::Received) {
             info!("queueing packet: {}", packet.id());
-            REGISTRY.queue(packet.clone());
+            REGISTRY.queue(packet.clone()).expect("registry queue");
             channel_send.send(packet).expect("channel");
         }
         count += 1;
@@ -231,56 +565,25 @@ fn main() {
     info!("registry initialized with {} packets found", count);
 
     // spawn workers
-    let mut worker_handles = Vec::with_capacity(NUMBER_OF_WORKERS);
-    for i in 0..NUMBER_OF_WORKERS {
-        let c_recv = channel_recv.clone();
-        let handle = thread::spawn(move || {
-            // init docker
-            let dock = Dock::new(format!("worker-{}", i)).expect("docker");
-
-            loop {
-                // wait for packet
-                let packet = match c_recv.recv() {
-                    Ok(pkt) => pkt,
-                    Err(err) => {
-                        error!(
-                            "[worker {}] unexpected error when receiving packets: {}",
-                            i, err
-                        );
-                        continue;
-                    }
-                };
-                let hash = packet.id().to_string();
-                info!("[worker {}] received packet: {}", i, hash);
-
-                // process the packet
-                match analyze(&dock, &REGISTRY, &packet) {
-                    Ok(result) => {
-                        match REGISTRY.save_result(packet, result) {
-                            Ok(_) => (),
-                            Err(e) => {
-                                error!("[worker {}] failed to save analysis result: {}", i, e);
-                            }
-                        };
-                        info!("[worker {}] packet analyzed: {}", i, hash);
-                    }
-                    Err(err) => {
-                        error!(
-                            "[worker {}] unexpected error when analyzing packet: {}",
-                            i, err
-                        );
-                        match REGISTRY.save_error(packet, err.to_string()) {
-                            Ok(_) => (),
-                            Err(e) => {
-                                error!("[worker {}] failed to save analysis error: {}", i, e);
-                            }
-                        };
-                    }
-                }
+    let worker_handles = pool::spawn_workers(&REGISTRY, &CORES, &channel_recv, NUMBER_OF_WORKERS);
+
+    // spawn the periodic garbage collector
+    thread::spawn(|| loop {
+        match retention::collect_garbage(&REGISTRY, DISK_QUOTA) {
+            Ok(report) => {
+                info!(
+                    "[gc] disk usage {} MiB -> {} MiB, {} packet(s) evicted",
+                    report.total_before / 1024 / 1024,
+                    report.total_after / 1024 / 1024,
+                    report.evicted.len()
+                );
             }
-        });
-        worker_handles.push(handle);
-    }
+            Err(err) => {
+                error!("[gc] unexpected error when collecting garbage: {}", err);
+            }
+        }
+        thread::sleep(GC_INTERVAL);
+    });
 
     // bind address
     let addr = SocketAddr::from(([0, 0, 0, 0], PORT));
@@ -308,9 +611,50 @@ fn main() {
 
             // process it
             let response = match Action::parse(&mut request) {
-                Ok(Action::Default) => make_ok(WELCOME.to_string()),
-                Ok(Action::Status(hash)) => handle_status(hash),
-                Ok(Action::Submit(body)) => handle_submit(body, &c_send),
+                Ok(Action::Default { html: false }) => make_ok(WELCOME.to_string()),
+                Ok(Action::Default { html: true }) => make_ok_html(WELCOME_HTML.as_str()),
+                Ok(Action::Status { hash, staff }) => handle_status(hash, staff),
+                Ok(Action::Plot(hash)) => handle_plot(hash),
+                Ok(Action::Hangs(hash)) => handle_hangs(hash),
+                Ok(Action::Gradebook {
+                    format,
+                    selection,
+                    assignment,
+                }) => handle_gradebook(format, selection, assignment),
+                Ok(Action::Progress { hash, since }) => {
+                    // long-polling requests are held in their own thread to keep the server free,
+                    // up to a limited number of them
+                    let slot = match LongPollSlot::acquire() {
+                        None => {
+                            let response =
+                                make_busy_error("too many progress requests held, retry later");
+                            if let Err(err) = request.respond(response) {
+                                error!(
+                                    "[server {}] unexpected error when sending response: {}",
+                                    i, err
+                                );
+                            }
+                            continue;
+                        }
+                        Some(slot) => slot,
+                    };
+                    thread::spawn(move || {
+                        let response = handle_progress(hash, since);
+                        drop(slot);
+                        if let Err(err) = request.respond(response) {
+                            error!(
+                                "[server {}] unexpected error when sending response: {}",
+                                i, err
+                            );
+                        }
+                    });
+                    continue;
+                }
+                Ok(Action::Submit {
+                    body,
+                    submitter,
+                    assignment,
+                }) => handle_submit(body, submitter, assignment, &c_send),
                 Err(err) => make_sanity_error(err.to_string()),
             };
 
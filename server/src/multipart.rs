@@ -0,0 +1,183 @@
+use anyhow::{anyhow, bail, Result};
+
+/// A single part in a multipart/form-data body
+pub struct Part {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Extract the boundary from a `Content-Type: multipart/form-data` header value
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut segs = content_type.split(';');
+    let mime = segs.next()?.trim();
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    for seg in segs {
+        let (key, val) = match seg.trim().split_once('=') {
+            None => continue,
+            Some(kv) => kv,
+        };
+        if key.trim().eq_ignore_ascii_case("boundary") {
+            let val = val.trim().trim_matches('"');
+            if !val.is_empty() {
+                return Some(val.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Find the first occurrence of a needle in a haystack
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Parse the value of a `Content-Disposition` parameter
+fn disposition_param(line: &str, key: &str) -> Option<String> {
+    for seg in line.split(';').skip(1) {
+        if let Some((k, v)) = seg.trim().split_once('=') {
+            if k.trim().eq_ignore_ascii_case(key) {
+                return Some(v.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Parse a multipart/form-data body into its parts
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{}", boundary);
+    let separator = format!("\r\n--{}", boundary);
+
+    // skip the preamble
+    let start =
+        find(body, delimiter.as_bytes()).ok_or_else(|| anyhow!("multipart boundary not found"))?;
+    let mut rest = &body[start + delimiter.len()..];
+
+    let mut parts = vec![];
+    loop {
+        // closing delimiter
+        if rest.starts_with(b"--") {
+            break;
+        }
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| anyhow!("malformed multipart delimiter"))?;
+
+        // headers
+        let split =
+            find(rest, b"\r\n\r\n").ok_or_else(|| anyhow!("malformed multipart headers"))?;
+        let headers = std::str::from_utf8(&rest[..split])?;
+        rest = &rest[split + 4..];
+
+        let mut name = None;
+        let mut filename = None;
+        for line in headers.split("\r\n") {
+            let (key, val) = match line.split_once(':') {
+                None => bail!("malformed multipart header: {}", line),
+                Some(kv) => kv,
+            };
+            if key.trim().eq_ignore_ascii_case("content-disposition") {
+                name = disposition_param(val, "name");
+                filename = disposition_param(val, "filename");
+            }
+        }
+
+        // content
+        let end = find(rest, separator.as_bytes())
+            .ok_or_else(|| anyhow!("unterminated multipart body"))?;
+        parts.push(Part {
+            name,
+            filename,
+            data: rest[..end].to_vec(),
+        });
+        rest = &rest[end + separator.len()..];
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boundaries() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=abc").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"a b\"").as_deref(),
+            Some("a b")
+        );
+        assert_eq!(
+            boundary("multipart/form-data; boundary=\"xyz\"").as_deref(),
+            Some("xyz")
+        );
+        assert!(boundary("multipart/form-data; boundary=\"\"").is_none());
+        assert!(boundary("multipart/form-data").is_none());
+        assert!(boundary("text/plain; boundary=abc").is_none());
+    }
+
+    #[test]
+    fn well_formed() {
+        let body = b"preamble\r\n--xyz\r\n\
+            Content-Disposition: form-data; name=\"submitter\"\r\n\r\n\
+            alice\r\n--xyz\r\n\
+            Content-Disposition: form-data; name=\"packet\"; filename=\"p.zip\"\r\n\
+            Content-Type: application/zip\r\n\r\n\
+            PK\x03\x04\r\n\x00\r\n--xyz--\r\nepilogue";
+        let parts = parse(body, "xyz").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name.as_deref(), Some("submitter"));
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].data, b"alice");
+        assert_eq!(parts[1].name.as_deref(), Some("packet"));
+        assert_eq!(parts[1].filename.as_deref(), Some("p.zip"));
+        assert_eq!(parts[1].data, b"PK\x03\x04\r\n\x00");
+    }
+
+    #[test]
+    fn quoted_boundary() {
+        let content_type = "multipart/form-data; boundary=\"--=_part\"";
+        let boundary = boundary(content_type).unwrap();
+        let body = b"----=_part\r\n\
+            Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+            1\r\n----=_part--\r\n";
+        let parts = parse(body, &boundary).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].data, b"1");
+    }
+
+    #[test]
+    fn missing_closing_delimiter() {
+        let body = b"--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1";
+        assert!(parse(body, "xyz").is_err());
+        let body = b"--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--xyz";
+        assert!(parse(body, "xyz").is_err());
+        assert!(parse(b"no delimiter at all", "xyz").is_err());
+    }
+
+    #[test]
+    fn no_disposition() {
+        let body = b"--xyz\r\nContent-Type: text/plain\r\n\r\nhello\r\n--xyz--";
+        let parts = parse(body, "xyz").unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].name, None);
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].data, b"hello");
+    }
+
+    #[test]
+    fn boundary_in_data() {
+        // only a boundary on a line of its own ends the part
+        let body = b"--xyz\r\n\
+            Content-Disposition: form-data; name=\"f\"; filename=\"f\"\r\n\r\n\
+            a--xyz\nb--xyz--\r\n--xyz--";
+        let parts = parse(body, "xyz").unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].data, b"a--xyz\nb--xyz--");
+    }
+}
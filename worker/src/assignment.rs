@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::rubric::Rubric;
+use crate::tool_aflpp::ConfigAFLpp;
+use crate::tool_mutation::ConfigMutation;
+use crate::tool_sanitizer::ConfigSanitizer;
+use crate::util_diff::CompareMode;
+
+/// Directory holding the assignment configurations, next to the registry root
+///
+/// Each assignment is configured by `<name>.json` in this directory, and the files it refers
+/// to (e.g., dictionaries) are placed under a `<name>/` directory alongside.
+pub const DIR_ASSIGNMENTS: &str = "assignment";
+
+/// Directory of the hidden test suite under the directory of an assignment, laid out like the
+/// tests of a packet: `input/`, `crash/`, and optionally `expected/`
+pub const DIR_HIDDEN: &str = "hidden";
+
+/// Test cases held by the instructors and run against every packet of an assignment
+pub(crate) struct HiddenSuite {
+    /// tests expected to run normally, by name
+    pub(crate) input: Vec<(String, PathBuf)>,
+    /// tests expected to crash the program, by name
+    pub(crate) crash: Vec<(String, PathBuf)>,
+    /// expected outputs of tests in `input`, by name
+    pub(crate) expected: BTreeMap<String, PathBuf>,
+}
+
+impl HiddenSuite {
+    /// Total number of tests
+    pub(crate) fn len(&self) -> usize {
+        self.input.len() + self.crash.len()
+    }
+
+    /// List the regular files of a sub-directory by name, empty if there is no such directory
+    fn list(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+        let mut files = vec![];
+        if !dir.exists() {
+            return Ok(files);
+        }
+        for item in fs::read_dir(dir)? {
+            let item = item?;
+            let name = item
+                .file_name()
+                .into_string()
+                .map_err(|n| anyhow!("non-unicode name in hidden tests: {:?}", n))?;
+            if name.starts_with('.') {
+                continue;
+            }
+            if !item.file_type()?.is_file() {
+                bail!("hidden test {} is not a file", name);
+            }
+            files.push((name, item.path()));
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Per-assignment configuration of the analysis pipeline
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Assignment {
+    #[serde(skip)]
+    name: Option<String>,
+    #[serde(skip)]
+    dir: PathBuf,
+    /// whether inputs running into a timeout (tests or fuzzing hangs) count as failures, as
+    /// they always did before being reported separately, an assignment may only relax this
+    pub(crate) hangs_as_failures: bool,
+    /// how the output of a test is compared against its expected output, if any
+    pub(crate) compare_output: CompareMode,
+    /// source file of the reference program to compare the behavior of the program against
+    pub(crate) reference: Option<String>,
+    /// weighted criteria scoring the analysis result
+    pub(crate) rubric: Rubric,
+    pub(crate) aflpp: ConfigAFLpp,
+    pub(crate) sanitizer: ConfigSanitizer,
+    pub(crate) mutation: ConfigMutation,
+}
+
+impl Default for Assignment {
+    fn default() -> Self {
+        Self {
+            name: None,
+            dir: PathBuf::new(),
+            hangs_as_failures: true,
+            compare_output: CompareMode::default(),
+            reference: None,
+            rubric: Rubric::default(),
+            aflpp: ConfigAFLpp::default(),
+            sanitizer: ConfigSanitizer::default(),
+            mutation: ConfigMutation::default(),
+        }
+    }
+}
+
+impl Assignment {
+    /// Check that an assignment name is sane
+    pub fn check_name(name: &str) -> Result<()> {
+        if name.is_empty() || name.len() > 64 {
+            bail!("invalid assignment: must be between 1 and 64 characters");
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+        {
+            bail!("invalid assignment: only alphanumeric characters and '_', '-' allowed");
+        }
+        Ok(())
+    }
+
+    /// Load the configuration of an assignment, or the defaults if no assignment is given
+    pub fn load(base: &Path, name: Option<&str>) -> Result<Self> {
+        let name = match name {
+            None => return Ok(Self::default()),
+            Some(name) => name,
+        };
+        Self::check_name(name)?;
+
+        let path = base.join(format!("{}.json", name));
+        if !path.is_file() {
+            bail!("unknown assignment: {}", name);
+        }
+        let mut assignment: Self = serde_json::from_reader(File::open(&path)?)
+            .map_err(|e| anyhow!("invalid configuration of assignment {}: {}", name, e))?;
+        assignment.name = Some(name.to_string());
+        assignment.dir = base.join(name);
+        assignment.validate()?;
+        Ok(assignment)
+    }
+
+    /// Name of the assignment, `None` for the defaults
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Resolve a file referred to by the configuration
+    pub(crate) fn resolve(&self, file: &str) -> Result<PathBuf> {
+        if file.is_empty() || file.contains('/') || file.starts_with('.') {
+            bail!("invalid file name in assignment configuration: {}", file);
+        }
+        let path = self.dir.join(file);
+        if !path.is_file() {
+            bail!("file {} not found for assignment", file);
+        }
+        Ok(path)
+    }
+
+    /// Load the hidden test suite of the assignment, `None` if it has none
+    pub(crate) fn hidden_suite(&self) -> Result<Option<HiddenSuite>> {
+        if self.name.is_none() {
+            return Ok(None);
+        }
+        let base = self.dir.join(DIR_HIDDEN);
+        if !base.is_dir() {
+            return Ok(None);
+        }
+        let suite = HiddenSuite {
+            input: HiddenSuite::list(&base.join("input"))?,
+            crash: HiddenSuite::list(&base.join("crash"))?,
+            expected: HiddenSuite::list(&base.join("expected"))?
+                .into_iter()
+                .collect(),
+        };
+        for name in suite.expected.keys() {
+            if !suite.input.iter().any(|(n, _)| n == name) {
+                bail!(
+                    "hidden expected/{} does not match any hidden test under input/",
+                    name
+                );
+            }
+        }
+        if suite.len() == 0 {
+            return Ok(None);
+        }
+        Ok(Some(suite))
+    }
+
+    /// Check the configuration as a whole
+    fn validate(&self) -> Result<()> {
+        self.aflpp.validate()?;
+        self.sanitizer.validate()?;
+        self.mutation.validate()?;
+        self.rubric.validate()?;
+        self.hidden_suite()?;
+        if let Some(reference) = self.reference.as_ref() {
+            self.resolve(reference)?;
+        }
+        for dict in &self.aflpp.dictionaries {
+            let path = self.resolve(dict)?;
+            if fs::metadata(path)?.len() > 1024 * 1024 {
+                bail!("dictionary {} is too big", dict);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+
+use anyhow::{bail, Result};
+use crossbeam_channel::Sender;
+use log::info;
+use serde::Serialize;
+use tempdir::TempDir;
+
+use crate::gradebook::{csv_field, Format};
+use crate::packet::{check_submitter, copy_dir_recursive, Packet, Registry, Status};
+use crate::pool;
+use crate::util_archive::extract;
+
+/// Maximum size of a package archive, the same as for a submission to the server
+const MAX_ARCHIVE_SIZE: u64 = 1024 * 1024;
+
+/// Suffixes of the archives accepted as packages, stripped to derive the student ID
+const ARCHIVE_SUFFIXES: &[&str] = &[".tar.gz", ".tgz", ".tar", ".zip"];
+
+/// A package found in the batch directory
+pub struct Item {
+    /// student ID, taken from the folder or archive name
+    pub submitter: String,
+    /// name of the folder or archive
+    pub source: String,
+    /// the registered packet, or why the package is rejected
+    pub packet: Result<Packet, String>,
+}
+
+/// Register every package in a directory, each being a folder or an archive named after the
+/// student who submitted it
+pub fn import(registry: &Registry, dir: &Path, assignment: Option<&str>) -> Result<Vec<Item>> {
+    let mut sources: Vec<PathBuf> = vec![];
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        if !hidden {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+
+    let mut items = vec![];
+    for path in sources {
+        let source = path
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string());
+        let submitter = if path.is_dir() {
+            source.clone()
+        } else {
+            ARCHIVE_SUFFIXES
+                .iter()
+                .find_map(|suffix| source.strip_suffix(suffix))
+                .unwrap_or(&source)
+                .to_string()
+        };
+        let packet = check_submitter(&submitter)
+            .and_then(|_| register(registry, &path, &submitter, assignment))
+            .map_err(|e| e.to_string());
+        match packet.as_ref() {
+            Ok(packet) => info!("[batch] {}: registered as {}", source, packet.id()),
+            Err(err) => info!("[batch] {}: rejected, {}", source, err),
+        }
+        items.push(Item {
+            submitter,
+            source,
+            packet,
+        });
+    }
+    Ok(items)
+}
+
+/// Register a package given as a folder or an archive
+fn register(
+    registry: &Registry,
+    path: &Path,
+    submitter: &str,
+    assignment: Option<&str>,
+) -> Result<Packet> {
+    // registration renames the tests in place, so it works on a copy of the package
+    let tmp = TempDir::new("pap-batch")?;
+    if path.is_dir() {
+        copy_dir_recursive(path, tmp.path())?;
+    } else {
+        if fs::metadata(path)?.len() > MAX_ARCHIVE_SIZE {
+            bail!("archive is too large");
+        }
+        extract(&fs::read(path)?, tmp.path())?;
+    }
+    let (packet, _) = registry.register(unwrap_folder(tmp.path())?, Some(submitter), assignment)?;
+    tmp.close()?;
+    Ok(packet)
+}
+
+/// Locate the package in a folder, which may be wrapped in a single sub-folder
+fn unwrap_folder(path: &Path) -> Result<PathBuf> {
+    if path.join("main.c").exists() {
+        return Ok(path.to_path_buf());
+    }
+    let mut dirs = vec![];
+    for item in fs::read_dir(path)? {
+        let item = item?;
+        if item.file_type()?.is_dir() {
+            dirs.push(item.path());
+        }
+    }
+    match dirs.as_slice() {
+        [inner] if inner.join("main.c").exists() => Ok(inner.clone()),
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+/// Analyze the packets registered from the batch that are yet to be analyzed
+pub fn analyze(
+    registry: &Registry,
+    channel: &Sender<Packet>,
+    workers: &[JoinHandle<()>],
+    items: &[Item],
+) -> Result<()> {
+    let packets: BTreeSet<_> = items
+        .iter()
+        .filter_map(|item| item.packet.as_ref().ok())
+        .cloned()
+        .collect();
+    pool::analyze_all(registry, channel, workers, &packets, "batch")
+}
+
+/// Outcome of a package in the batch
+#[derive(Serialize)]
+pub struct ReportEntry {
+    pub submitter: String,
+    pub source: String,
+    pub packet: Option<String>,
+    /// `rejected` if the package could not be registered, or the status of its analysis
+    pub status: String,
+    /// why the package is rejected or its analysis failed
+    pub message: Option<String>,
+    pub score: Option<f64>,
+    pub score_total: Option<f64>,
+}
+
+/// Consolidate the outcomes of the packages in the batch
+pub fn report(registry: &Registry, items: &[Item]) -> Result<Vec<ReportEntry>> {
+    let snapshot = registry.snapshot()?;
+    let mut entries = vec![];
+    for item in items {
+        let mut entry = ReportEntry {
+            submitter: item.submitter.clone(),
+            source: item.source.clone(),
+            packet: None,
+            status: "rejected".to_string(),
+            message: None,
+            score: None,
+            score_total: None,
+        };
+        match item.packet.as_ref() {
+            Err(err) => entry.message = Some(err.clone()),
+            Ok(packet) => {
+                entry.packet = Some(packet.id().to_string());
+                let status = snapshot.get(packet).copied().unwrap_or(Status::Error);
+                entry.status = status.as_str().to_string();
+                match status {
+                    Status::Completed => {
+                        let result = registry.read_result(packet)?;
+                        if let Some(score) = result.rubric.as_ref() {
+                            entry.score = Some(score.score);
+                            entry.score_total = Some(score.total);
+                        }
+                    }
+                    Status::Error => {
+                        entry.message =
+                            registry.load_packet_status(packet.id().to_string(), true)?;
+                    }
+                    Status::Received | Status::Running => (),
+                }
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Render the report in the given format
+pub fn render(entries: &[ReportEntry], format: Format) -> Result<String> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(entries)?),
+        Format::Csv => {
+            let number = |v: Option<f64>| v.map_or_else(String::new, |v| format!("{:.2}", v));
+            let mut lines =
+                vec!["submitter,source,packet,status,score,score_total,message".to_string()];
+            for entry in entries {
+                let row = [
+                    entry.submitter.clone(),
+                    entry.source.clone(),
+                    entry.packet.clone().unwrap_or_default(),
+                    entry.status.clone(),
+                    number(entry.score),
+                    number(entry.score_total),
+                    entry.message.clone().unwrap_or_default(),
+                ];
+                let row: Vec<_> = row.iter().map(|field| csv_field(field)).collect();
+                lines.push(row.join(","));
+            }
+            lines.push(String::new());
+            Ok(lines.join("\n"))
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeSet;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use log::info;
+
+/// Bookkeeping of the free cores and of the waiting line for them
+struct CoreState {
+    free: BTreeSet<usize>,
+    next_ticket: u64,
+    serving: u64,
+}
+
+/// Pool of CPU cores reserved exclusively by fuzzing containers
+pub struct CorePool {
+    total: usize,
+    state: Mutex<CoreState>,
+    released: Condvar,
+}
+
+impl CorePool {
+    /// Create a pool of cores numbered from 0 to `total - 1`
+    pub fn new(total: usize) -> Self {
+        let total = total.max(1);
+        Self {
+            total,
+            state: Mutex::new(CoreState {
+                free: (0..total).collect(),
+                next_ticket: 0,
+                serving: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Create a pool of all cores available on this host
+    pub fn detect() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    /// Total number of cores in the pool
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Number of cores not reserved at the moment
+    pub fn available(&self) -> usize {
+        let locked = self.state.lock().expect("lock");
+        let count = locked.free.len();
+        drop(locked);
+        count
+    }
+
+    /// Reserve a number of cores (at most all of them), waiting in first-come-first-served
+    /// order until enough cores are free
+    pub fn reserve(&self, count: usize) -> Reservation<'_> {
+        let count = count.clamp(1, self.total);
+
+        let mut locked = self.state.lock().expect("lock");
+        let ticket = locked.next_ticket;
+        locked.next_ticket += 1;
+        if locked.serving != ticket || locked.free.len() < count {
+            info!(
+                "waiting for {} core(s), {} free at the moment",
+                count,
+                locked.free.len()
+            );
+        }
+        while locked.serving != ticket || locked.free.len() < count {
+            locked = self.released.wait(locked).expect("lock");
+        }
+        let cores: Vec<_> = locked.free.iter().take(count).copied().collect();
+        for core in &cores {
+            locked.free.remove(core);
+        }
+        locked.serving += 1;
+        drop(locked);
+
+        // the next in line may be satisfied as well
+        self.released.notify_all();
+        Reservation { pool: self, cores }
+    }
+}
+
+/// Cores reserved from the pool, returned when dropped
+pub struct Reservation<'a> {
+    pool: &'a CorePool,
+    cores: Vec<usize>,
+}
+
+impl Reservation<'_> {
+    /// The reserved cores
+    pub fn cores(&self) -> &[usize] {
+        &self.cores
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut locked = self.pool.state.lock().expect("lock");
+        locked.free.extend(self.cores.drain(..));
+        drop(locked);
+        self.pool.released.notify_all();
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::packet::{
+    hash_stored, is_hash, is_staging, Packet, Registry, Status, FILE_DATABASE, MARKER_RESULT,
+    SUFFIX_TEMP,
+};
+use crate::schema;
+
+/// Items every packet must have in its directory
+const PACKET_SOURCES: &[&str] = &["main.c", "interface.h", "input", "crash"];
+
+/// Age after which the staging directory of a registration is taken as abandoned
+const STAGING_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// An inconsistency found in the registry
+pub enum Issue {
+    /// An entry in the registry root that is neither a packet, the database, nor the staging
+    /// directory of a registration in progress
+    StrayEntry(PathBuf),
+    /// A leftover temporary file from an interrupted write
+    StrayTemp(PathBuf),
+    /// A packet directory without a metadata record
+    MissingRecord(Packet),
+    /// A metadata record without a packet directory
+    MissingDirectory(Packet),
+    /// A packet directory that holds nothing but analysis outputs
+    OrphanedOutput(Packet),
+    /// A packet directory with some of its sources missing
+    MissingSource(Packet, &'static str),
+    /// A packet whose content no longer matches its hash
+    HashMismatch(Packet, String),
+    /// A completed packet without a result file
+    MissingResult(Packet),
+    /// A completed packet whose result file cannot be parsed
+    HalfWrittenResult(Packet),
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StrayEntry(path) => write!(f, "stray entry: {:?}", path),
+            Self::StrayTemp(path) => write!(f, "stray temporary file: {:?}", path),
+            Self::MissingRecord(pkt) => write!(f, "{}: no metadata record", pkt.id()),
+            Self::MissingDirectory(pkt) => write!(f, "{}: no packet directory", pkt.id()),
+            Self::OrphanedOutput(pkt) => write!(f, "{}: orphaned output", pkt.id()),
+            Self::MissingSource(pkt, item) => write!(f, "{}: {} is missing", pkt.id(), item),
+            Self::HashMismatch(pkt, actual) => {
+                write!(f, "{}: content hashes to {}", pkt.id(), actual)
+            }
+            Self::MissingResult(pkt) => write!(f, "{}: completed without result", pkt.id()),
+            Self::HalfWrittenResult(pkt) => write!(f, "{}: result cannot be parsed", pkt.id()),
+        }
+    }
+}
+
+impl Issue {
+    /// Fix the inconsistency, mostly by setting aside what cannot be trusted
+    fn repair(&self, registry: &Registry) -> Result<()> {
+        match self {
+            Self::StrayEntry(path) => {
+                let dest = registry.quarantine_entry(path)?;
+                info!("{:?} quarantined in {:?}", path, dest);
+            }
+            Self::StrayTemp(path) => remove_path(path)?,
+            Self::MissingRecord(pkt) => registry.recover_record(pkt)?,
+            Self::MissingDirectory(pkt) => registry.forget(pkt)?,
+            // nothing is deleted on a suspicion, the packet and its history are set aside
+            Self::OrphanedOutput(pkt)
+            | Self::MissingSource(pkt, _)
+            | Self::HashMismatch(pkt, _) => {
+                let dest = registry.quarantine(pkt)?;
+                info!("packet {} quarantined in {:?}", pkt.id(), dest);
+            }
+            Self::MissingResult(pkt) | Self::HalfWrittenResult(pkt) => {
+                registry.invalidate_result(pkt)?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove a path regardless of whether it is a file or a directory
+fn remove_path(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Check a single packet directory
+fn check_packet(packet: &Packet, path: &Path, status: Option<Status>, issues: &mut Vec<Issue>) {
+    // temporary files from interrupted writes
+    if let Ok(items) = fs::read_dir(path) {
+        for item in items.flatten() {
+            let item_path = item.path();
+            if item_path.extension().is_some_and(|ext| ext == SUFFIX_TEMP) {
+                issues.push(Issue::StrayTemp(item_path));
+            }
+        }
+    }
+
+    // sources
+    let missing: Vec<_> = PACKET_SOURCES
+        .iter()
+        .filter(|item| !path.join(item).exists())
+        .collect();
+    if missing.len() == PACKET_SOURCES.len() {
+        issues.push(Issue::OrphanedOutput(packet.clone()));
+        return;
+    }
+    if let Some(item) = missing.first() {
+        issues.push(Issue::MissingSource(packet.clone(), item));
+        return;
+    }
+
+    // content integrity
+    match hash_stored(path) {
+        Ok(actual) if actual == packet.id() => (),
+        Ok(actual) => {
+            issues.push(Issue::HashMismatch(packet.clone(), actual));
+            return;
+        }
+        Err(err) => {
+            issues.push(Issue::HashMismatch(packet.clone(), format!("<{}>", err)));
+            return;
+        }
+    }
+
+    // result of completed analysis
+    if matches!(status, Some(Status::Completed)) {
+        let path_result = path.join(MARKER_RESULT);
+        if !path_result.exists() {
+            issues.push(Issue::MissingResult(packet.clone()));
+        } else {
+            // results of older schema versions are fine as long as they can be migrated
+            if schema::load(&path_result).is_err() {
+                issues.push(Issue::HalfWrittenResult(packet.clone()));
+            }
+        }
+    }
+}
+
+/// Check the registry for inconsistencies, optionally repairing them
+pub fn check(registry: &Registry, repair: bool) -> Result<Vec<Issue>> {
+    let root = registry.root();
+    let mut issues = vec![];
+
+    // packets known to the metadata store
+    let mut known = BTreeSet::new();
+    for record in registry.records()? {
+        let packet = Packet::from_id(record.hash.clone());
+        let status = Status::parse(&record.status).ok();
+        let path = registry.packet_path(&packet);
+        if !path.is_dir() {
+            issues.push(Issue::MissingDirectory(packet.clone()));
+        } else {
+            check_packet(&packet, &path, status, &mut issues);
+        }
+        known.insert(record.hash);
+    }
+
+    // entries on disk
+    for item in fs::read_dir(&root)? {
+        let item = item?;
+        let path = item.path();
+        let name = match item.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => {
+                issues.push(Issue::StrayEntry(path));
+                continue;
+            }
+        };
+        if name.starts_with(FILE_DATABASE) && item.file_type()?.is_file() {
+            continue;
+        }
+        if is_staging(&name) && item.file_type()?.is_dir() {
+            // registrations take seconds, only the ones left behind long ago are stray
+            let age = item
+                .metadata()?
+                .modified()?
+                .elapsed()
+                .unwrap_or(Duration::ZERO);
+            if age < STAGING_MAX_AGE {
+                continue;
+            }
+        }
+        if !item.file_type()?.is_dir() || !is_hash(&name) {
+            issues.push(Issue::StrayEntry(path));
+            continue;
+        }
+        if known.contains(&name) {
+            continue;
+        }
+
+        // unknown packets are only recovered when they are otherwise intact
+        let packet = Packet::from_id(name);
+        let count = issues.len();
+        check_packet(&packet, &path, None, &mut issues);
+        if issues.len() == count {
+            issues.push(Issue::MissingRecord(packet));
+        }
+    }
+
+    // repair if requested
+    if repair {
+        for issue in &issues {
+            match issue.repair(registry) {
+                Ok(()) => info!("repaired {}", issue),
+                Err(err) => warn!("unable to repair {}: {}", issue, err),
+            }
+        }
+    }
+    Ok(issues)
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use log::warn;
+use serde::Serialize;
+
+use crate::packet::{Packet, Registry, Status};
+use crate::rubric::Metric;
+
+/// Which submissions are covered by a gradebook
+#[derive(Copy, Clone)]
+pub enum Selection {
+    /// every submission of every packet
+    All,
+    /// the latest submission of each student, per assignment
+    LatestPerStudent,
+}
+
+/// Formats a gradebook can be exported in
+#[derive(Copy, Clone)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Self> {
+        let format = match s {
+            "csv" => Self::Csv,
+            "json" => Self::Json,
+            _ => bail!("invalid gradebook format: {}", s),
+        };
+        Ok(format)
+    }
+}
+
+/// A submission in the gradebook, with the key metrics and the score of its analysis result
+#[derive(Serialize)]
+pub struct Entry {
+    pub submitter: Option<String>,
+    pub assignment: Option<String>,
+    pub packet: String,
+    /// submission time, in RFC 3339 format (UTC)
+    pub submitted_at: String,
+    /// time the analysis completed or failed, in RFC 3339 format (UTC)
+    pub analyzed_at: Option<String>,
+    pub status: String,
+    pub score: Option<f64>,
+    pub score_total: Option<f64>,
+    /// metrics of the analysis result (see the rubric), by name
+    pub metrics: BTreeMap<&'static str, Option<f64>>,
+}
+
+/// Collect the gradebook of the registry, optionally restricted to an assignment
+pub fn collect(
+    registry: &Registry,
+    selection: Selection,
+    assignment: Option<&str>,
+) -> Result<Vec<Entry>> {
+    let mut entries = vec![];
+    for record in registry.records()? {
+        if assignment.is_some() && record.assignment.as_deref() != assignment {
+            continue;
+        }
+        let packet = Packet::from_id(record.hash.clone());
+        let mut status = Status::parse(&record.status)?;
+
+        // the result is read without counting as an access, so exports do not affect retention;
+        // a result that cannot be read fails its rows only, not the whole export
+        let result = match status {
+            Status::Completed => match registry.read_result(&packet) {
+                Ok(result) => Some(result),
+                Err(err) => {
+                    warn!(
+                        "unable to read the result of packet {}: {}",
+                        packet.id(),
+                        err
+                    );
+                    status = Status::Error;
+                    None
+                }
+            },
+            _ => None,
+        };
+        let analyzed_at = match status {
+            Status::Completed | Status::Error => Some(fmt_timestamp(record.updated_at)),
+            Status::Received | Status::Running => None,
+        };
+        let score = result.as_ref().and_then(|r| r.rubric.as_ref());
+        let metrics: BTreeMap<_, _> = Metric::ALL
+            .iter()
+            .map(|m| (m.as_str(), result.as_ref().and_then(|r| m.evaluate(r))))
+            .collect();
+
+        // packets registered before submissions were recorded count as one anonymous submission
+        let mut submissions: Vec<_> = registry
+            .submissions(&packet)?
+            .into_iter()
+            .map(|s| (s.submitter, s.submitted_at))
+            .collect();
+        if submissions.is_empty() {
+            submissions.push((None, record.created_at));
+        }
+        for (submitter, submitted_at) in submissions {
+            entries.push((
+                submitted_at,
+                Entry {
+                    submitter,
+                    assignment: record.assignment.clone(),
+                    packet: record.hash.clone(),
+                    submitted_at: fmt_timestamp(submitted_at),
+                    analyzed_at: analyzed_at.clone(),
+                    status: status.as_str().to_string(),
+                    score: score.map(|s| s.score),
+                    score_total: score.map(|s| s.total),
+                    metrics: metrics.clone(),
+                },
+            ));
+        }
+    }
+
+    // keep the latest submission of each student if asked to
+    if matches!(selection, Selection::LatestPerStudent) {
+        let mut latest: BTreeMap<(String, Option<String>), (i64, Entry)> = BTreeMap::new();
+        for (submitted_at, entry) in entries {
+            let submitter = match entry.submitter.as_ref() {
+                None => continue,
+                Some(submitter) => submitter.clone(),
+            };
+            let key = (submitter, entry.assignment.clone());
+            if latest.get(&key).is_none_or(|(t, _)| *t <= submitted_at) {
+                latest.insert(key, (submitted_at, entry));
+            }
+        }
+        entries = latest.into_values().collect();
+    }
+
+    entries.sort_by(|(t1, e1), (t2, e2)| {
+        (&e1.submitter, &e1.assignment, t1).cmp(&(&e2.submitter, &e2.assignment, t2))
+    });
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+/// Render a gradebook in the given format
+pub fn render(entries: &[Entry], format: Format) -> Result<String> {
+    match format {
+        Format::Csv => Ok(to_csv(entries)),
+        Format::Json => Ok(serde_json::to_string_pretty(entries)?),
+    }
+}
+
+/// Render a gradebook as CSV, one row per entry, with the metrics as columns
+fn to_csv(entries: &[Entry]) -> String {
+    let mut header = vec![
+        "submitter",
+        "assignment",
+        "packet",
+        "submitted_at",
+        "analyzed_at",
+        "status",
+        "score",
+        "score_total",
+    ];
+    header.extend(Metric::ALL.iter().map(|m| m.as_str()));
+
+    let number = |v: Option<f64>| v.map_or_else(String::new, |v| format!("{:.2}", v));
+    let mut lines = vec![header.join(",")];
+    for entry in entries {
+        let mut row = vec![
+            entry.submitter.clone().unwrap_or_default(),
+            entry.assignment.clone().unwrap_or_default(),
+            entry.packet.clone(),
+            entry.submitted_at.clone(),
+            entry.analyzed_at.clone().unwrap_or_default(),
+            entry.status.clone(),
+            number(entry.score),
+            number(entry.score_total),
+        ];
+        row.extend(
+            Metric::ALL
+                .iter()
+                .map(|m| number(entry.metrics.get(m.as_str()).copied().flatten())),
+        );
+        let row: Vec<_> = row.iter().map(|field| csv_field(field)).collect();
+        lines.push(row.join(","));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Quote a CSV field if needed, defusing text that spreadsheets would take for a formula (e.g.,
+/// a submitter named `-2+3` or `@SUM(A1)`) with a leading `'`
+pub(crate) fn csv_field(field: &str) -> String {
+    let formula =
+        field.starts_with(['=', '+', '-', '@', '\t', '\r']) && field.parse::<f64>().is_err();
+    let field = if formula {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Format seconds since UNIX epoch in RFC 3339 format (UTC)
+fn fmt_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+
+    // civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(csv_field("alice"), "alice");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("-1.50"), "-1.50");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn csv_formulas() {
+        assert_eq!(csv_field("=1+2"), "'=1+2");
+        assert_eq!(csv_field("+1-2"), "'+1-2");
+        assert_eq!(csv_field("-2+3"), "'-2+3");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("-cmd,x"), "\"'-cmd,x\"");
+        assert_eq!(csv_field("a-b@c"), "a-b@c");
+    }
+}
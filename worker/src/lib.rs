@@ -1,7 +1,25 @@
+pub mod util_archive;
+pub mod util_clex;
+pub mod util_diff;
 pub mod util_docker;
 
 pub(crate) mod tool_aflpp;
+pub(crate) mod tool_aflpp_stats;
+pub(crate) mod tool_differential;
 pub(crate) mod tool_gcov;
+pub(crate) mod tool_mutation;
+pub(crate) mod tool_sanitizer;
 
+pub mod assignment;
+pub mod batch;
+pub mod cores;
+pub mod fsck;
+pub mod gradebook;
+pub mod metadata;
 pub mod packet;
+pub mod pool;
 pub mod process;
+pub mod regrade;
+pub mod retention;
+pub mod rubric;
+pub mod schema;
@@ -1,25 +1,308 @@
-use cs453_pap_worker::process;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use log::info;
+
+use cs453_pap_worker::cores::CorePool;
+use cs453_pap_worker::gradebook::{Format, Selection};
+use cs453_pap_worker::packet::{Packet, Registry, Status};
+use cs453_pap_worker::process::PIPELINE_VERSION;
+use cs453_pap_worker::schema::RESULT_SCHEMA_VERSION;
+use cs453_pap_worker::{batch, fsck, gradebook, pool, process, regrade, retention, schema};
+
+/// Command line usage
+const USAGE: &str = "\
+usage: cs453-pap-worker [command]
+
+commands:
+    provision              build the Docker images of all tools (default)
+    gc [--quota <GiB>]     compact and evict packet artifacts to enforce the disk quota
+    fsck [--repair]        check the registry for inconsistencies (stop the server to repair)
+    upgrade                upgrade stored results to the current schema in place (stop the server)
+    gradebook [--latest] [--assignment <name>] [--format csv|json]
+                           export the results of all submissions (or the latest per student)
+    batch <dir> [--assignment <name>] [--workers <n>] [--format csv|json]
+                           register and analyze a directory of packages named by student ID
+                           (one folder or archive each) and report on them (stop the server)
+    regrade [--assignment <name>] [--workers <n>] [--format csv|json]
+                           re-analyze packets analyzed by an older version of the pipeline and
+                           report on the results that changed (stop the server)
+";
+
+/// Default number of workers analyzing a batch (or a re-grade)
+const DEFAULT_BATCH_WORKERS: usize = 4;
+
+/// Absolute path to the `data` directory
+fn registry_root() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    assert!(path.pop());
+    path.push("data");
+    path
+}
+
+/// Provision all the tools
+fn cmd_provision() -> Result<()> {
+    // check if we need to force provision
+    let force = match std::env::var_os("FORCE_PROVISION") {
+        None => false,
+        Some(v) => v.to_str() == Some("1"),
+    };
+    process::provision(force)
+}
+
+/// Run a round of garbage collection on the registry
+fn cmd_gc(args: &[String]) -> Result<()> {
+    let quota = match args {
+        [] => retention::DEFAULT_DISK_QUOTA,
+        [flag, value] if flag == "--quota" => {
+            let gib: u64 = value
+                .parse()
+                .map_err(|_| anyhow!("invalid quota: {}", value))?;
+            gib * 1024 * 1024 * 1024
+        }
+        _ => bail!("invalid arguments for gc\n{}", USAGE),
+    };
+
+    let registry = Registry::open(registry_root())?;
+
+    // compact packets analyzed before the retention policy was in place
+    for record in registry.records()? {
+        if matches!(Status::parse(&record.status)?, Status::Completed)
+            && record.evicted_at.is_none()
+        {
+            retention::compact(&registry, &Packet::from_id(record.hash))?;
+        }
+    }
+
+    // evict artifacts beyond the quota
+    let report = retention::collect_garbage(&registry, quota)?;
+    info!(
+        "disk usage {} MiB -> {} MiB, {} packet(s) evicted",
+        report.total_before / 1024 / 1024,
+        report.total_after / 1024 / 1024,
+        report.evicted.len()
+    );
+    for packet in report.evicted {
+        println!("{}", packet.id());
+    }
+    Ok(())
+}
+
+/// Check (and optionally repair) the integrity of the registry
+fn cmd_fsck(args: &[String]) -> Result<()> {
+    let repair = match args {
+        [] => false,
+        [flag] if flag == "--repair" => true,
+        _ => bail!("invalid arguments for fsck\n{}", USAGE),
+    };
+
+    let registry = Registry::open(registry_root())?;
+    let issues = fsck::check(&registry, repair)?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    info!(
+        "{} issue(s) found{}",
+        issues.len(),
+        if repair { " and repaired" } else { "" }
+    );
+    Ok(())
+}
+
+/// Upgrade the stored results of all packets to the current schema
+fn cmd_upgrade(args: &[String]) -> Result<()> {
+    if !args.is_empty() {
+        bail!("invalid arguments for upgrade\n{}", USAGE);
+    }
+
+    let registry = Registry::open(registry_root())?;
+    let upgrades = schema::upgrade_all(&registry)?;
+    let mut failed = 0;
+    for upgrade in &upgrades {
+        match upgrade.outcome.as_ref() {
+            Ok(version) => println!(
+                "{}: upgraded from version {}",
+                upgrade.path.display(),
+                version
+            ),
+            Err(err) => {
+                println!("{}: {}", upgrade.path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    let upgraded = upgrades.len() - failed;
+    info!(
+        "{} result(s) upgraded to schema version {}, {} failed",
+        upgraded, RESULT_SCHEMA_VERSION, failed
+    );
+    Ok(())
+}
+
+/// Export the gradebook to standard output
+fn cmd_gradebook(args: &[String]) -> Result<()> {
+    let mut selection = Selection::All;
+    let mut assignment = None;
+    let mut format = Format::Csv;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| anyhow!("missing value for {}\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--latest" => selection = Selection::LatestPerStudent,
+            "--assignment" => assignment = Some(value()?.as_str()),
+            "--format" => format = Format::parse(value()?)?,
+            _ => bail!("invalid arguments for gradebook\n{}", USAGE),
+        }
+    }
+
+    let registry = Registry::open(registry_root())?;
+    let entries = gradebook::collect(&registry, selection, assignment)?;
+    print!("{}", gradebook::render(&entries, format)?);
+    info!("{} entries exported", entries.len());
+    Ok(())
+}
+
+/// Register and analyze a directory of packages, one per student
+fn cmd_batch(args: &[String]) -> Result<()> {
+    let mut dir = None;
+    let mut assignment = None;
+    let mut workers = DEFAULT_BATCH_WORKERS;
+    let mut format = Format::Csv;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| anyhow!("missing value for {}\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--assignment" => assignment = Some(value()?.as_str()),
+            "--workers" => {
+                let v = value()?;
+                workers = v
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow!("invalid number of workers: {}", v))?;
+            }
+            "--format" => format = Format::parse(value()?)?,
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(Path::new(arg)),
+            _ => bail!("invalid arguments for batch\n{}", USAGE),
+        }
+    }
+    let dir = match dir {
+        None => bail!("missing directory for batch\n{}", USAGE),
+        Some(dir) if !dir.is_dir() => bail!("not a directory: {}", dir.display()),
+        Some(dir) => dir,
+    };
+
+    // the workers need the registry and the cores for the rest of the program
+    let root = registry_root();
+    fs::create_dir_all(&root)?;
+    let registry: &'static Registry = Box::leak(Box::new(Registry::new(root)?));
+    let cores: &'static CorePool = Box::leak(Box::new(CorePool::detect()));
+    registry.load_assignment(assignment)?;
+
+    let items = batch::import(registry, dir, assignment)?;
+    let (channel_send, channel_recv) = crossbeam_channel::unbounded();
+    let handles = pool::spawn_workers(registry, cores, &channel_recv, workers);
+    batch::analyze(registry, &channel_send, &handles, &items)?;
+
+    let entries = batch::report(registry, &items)?;
+    print!("{}", batch::render(&entries, format)?);
+    info!(
+        "{} package(s) in the batch, {} rejected",
+        entries.len(),
+        items.iter().filter(|item| item.packet.is_err()).count()
+    );
+    Ok(())
+}
+
+/// Re-analyze the packets analyzed by an older version of the pipeline
+fn cmd_regrade(args: &[String]) -> Result<()> {
+    let mut assignment = None;
+    let mut workers = DEFAULT_BATCH_WORKERS;
+    let mut format = Format::Csv;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| anyhow!("missing value for {}\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--assignment" => assignment = Some(value()?.as_str()),
+            "--workers" => {
+                let v = value()?;
+                workers = v
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow!("invalid number of workers: {}", v))?;
+            }
+            "--format" => format = Format::parse(value()?)?,
+            _ => bail!("invalid arguments for regrade\n{}", USAGE),
+        }
+    }
+
+    // the workers need the registry and the cores for the rest of the program
+    let root = registry_root();
+    fs::create_dir_all(&root)?;
+    let registry: &'static Registry = Box::leak(Box::new(Registry::new(root)?));
+    let cores: &'static CorePool = Box::leak(Box::new(CorePool::detect()));
+
+    let retired = regrade::retire(registry, assignment)?;
+    let packets = retired.iter().map(|item| item.packet.clone()).collect();
+    let (channel_send, channel_recv) = crossbeam_channel::unbounded();
+    let handles = pool::spawn_workers(registry, cores, &channel_recv, workers);
+    pool::analyze_all(registry, &channel_send, &handles, &packets, "regrade")?;
+
+    let changes = regrade::report(registry, &retired)?;
+    print!("{}", regrade::render(&changes, format)?);
+    info!(
+        "{} packet(s) re-graded under pipeline version {}, {} changed",
+        retired.len(),
+        PIPELINE_VERSION,
+        changes.len()
+    );
+    Ok(())
+}
 
 fn main() {
     // setup logging
     stderrlog::new()
         .module(module_path!())
+        .module("cs453_pap_worker")
         .timestamp(stderrlog::Timestamp::Second)
         .verbosity(stderrlog::LogLevelNum::Info)
         .init()
         .expect("unable to setup logging");
 
-    // check if we need to force provision
-    let force = match std::env::var_os("FORCE_PROVISION") {
-        None => false,
-        Some(v) => v.to_str().map_or(false, |v| v == "1"),
-    };
-
     // handle the command line
-    match process::provision(force) {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (name, result) = match args.first().map(|s| s.as_str()) {
+        None | Some("provision") => ("provision tools", cmd_provision()),
+        Some("gc") => ("collect garbage", cmd_gc(&args[1..])),
+        Some("fsck") => ("check registry", cmd_fsck(&args[1..])),
+        Some("upgrade") => ("upgrade results", cmd_upgrade(&args[1..])),
+        Some("gradebook") => ("export gradebook", cmd_gradebook(&args[1..])),
+        Some("batch") => ("analyze batch", cmd_batch(&args[1..])),
+        Some("regrade") => ("re-grade packets", cmd_regrade(&args[1..])),
+        Some("help" | "-h" | "--help") => {
+            print!("{}", USAGE);
+            return;
+        }
+        Some(cmd) => {
+            eprint!("unknown command: {}\n{}", cmd, USAGE);
+            std::process::exit(1);
+        }
+    };
+    match result {
         Ok(()) => (),
         Err(err) => {
-            panic!("failed to provision tools: {}", err);
+            panic!("failed to {}: {}", name, err);
         }
     }
 }
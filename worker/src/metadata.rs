@@ -0,0 +1,506 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+/// Schema of the metadata database
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS packets (
+    hash TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS submissions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hash TEXT NOT NULL REFERENCES packets(hash),
+    submitter TEXT,
+    submitted_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS attempts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hash TEXT NOT NULL REFERENCES packets(hash),
+    worker TEXT,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    outcome TEXT,
+    message TEXT
+);
+CREATE TABLE IF NOT EXISTS stages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    attempt INTEGER NOT NULL REFERENCES attempts(id),
+    stage TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    started_ms INTEGER NOT NULL,
+    finished_at INTEGER,
+    duration_ms INTEGER
+);
+CREATE INDEX IF NOT EXISTS submissions_hash ON submissions(hash);
+CREATE INDEX IF NOT EXISTS attempts_hash ON attempts(hash);
+CREATE INDEX IF NOT EXISTS stages_attempt ON stages(attempt);
+";
+
+/// Incremental schema upgrades on top of the base schema, tracked by `user_version`
+const SCHEMA_UPGRADES: &[&str] = &[
+    // 1: access and eviction timestamps for artifact retention
+    "ALTER TABLE packets ADD COLUMN accessed_at INTEGER;
+     ALTER TABLE packets ADD COLUMN evicted_at INTEGER;",
+    // 2: assignment the packet is submitted for
+    "ALTER TABLE packets ADD COLUMN assignment TEXT;",
+    // 3: original names of the test cases, which are stored under their indices
+    "CREATE TABLE test_cases (
+        hash TEXT NOT NULL REFERENCES packets(hash),
+        kind TEXT NOT NULL,
+        idx INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (hash, kind, idx)
+     );",
+    // 4: version of the analysis pipeline that produced the result
+    "ALTER TABLE packets ADD COLUMN pipeline INTEGER;",
+];
+
+/// Key in the meta table marking that the directory layout has been migrated
+const META_MIGRATED: &str = "migrated";
+
+/// Current time as seconds since UNIX epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Current time as milliseconds since UNIX epoch
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// A record of a packet in the metadata store
+#[derive(Serialize)]
+pub struct PacketRecord {
+    pub hash: String,
+    pub status: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub accessed_at: Option<i64>,
+    pub evicted_at: Option<i64>,
+    pub assignment: Option<String>,
+    /// version of the analysis pipeline that produced the result, `None` if it predates versioning
+    pub pipeline: Option<u32>,
+}
+
+impl PacketRecord {
+    /// Columns to select for constructing a record
+    const COLUMNS: &'static str =
+        "hash, status, created_at, updated_at, accessed_at, evicted_at, assignment, pipeline";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            hash: row.get(0)?,
+            status: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            accessed_at: row.get(4)?,
+            evicted_at: row.get(5)?,
+            assignment: row.get(6)?,
+            pipeline: row.get(7)?,
+        })
+    }
+
+    /// Time of last access, for least-recently-used eviction
+    pub fn last_access(&self) -> i64 {
+        self.accessed_at.unwrap_or(self.created_at)
+    }
+}
+
+/// A record of a submission event
+#[derive(Serialize)]
+pub struct SubmissionRecord {
+    pub submitter: Option<String>,
+    pub submitted_at: i64,
+}
+
+/// A record of a stage in an analysis attempt
+#[derive(Serialize)]
+pub struct StageRecord {
+    pub stage: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub duration_ms: Option<i64>,
+}
+
+/// A record of an analysis attempt
+#[derive(Serialize)]
+pub struct AttemptRecord {
+    pub worker: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub outcome: Option<String>,
+    pub message: Option<String>,
+    pub stages: Vec<StageRecord>,
+}
+
+/// Persistent metadata store for the registry, backed by SQLite
+pub struct MetaStore {
+    conn: Mutex<Connection>,
+}
+
+impl MetaStore {
+    /// Open (or create) the metadata store at the given path
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.execute_batch(SCHEMA)?;
+
+        // apply schema upgrades not seen by this database yet
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, upgrade) in SCHEMA_UPGRADES.iter().enumerate().skip(version) {
+            conn.execute_batch(upgrade)?;
+            conn.pragma_update(None, "user_version", i + 1)?;
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Check whether the one-shot migration from the directory layout is done
+    pub fn is_migrated(&self) -> Result<bool> {
+        let conn = self.conn.lock().expect("lock");
+        let value: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![META_MIGRATED],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value.is_some())
+    }
+
+    /// Mark the one-shot migration as done
+    pub fn set_migrated(&self) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+            params![META_MIGRATED, now().to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Insert a packet if it does not exist yet, returns whether it is inserted
+    pub fn add_packet(
+        &self,
+        hash: &str,
+        status: &str,
+        created_at: i64,
+        assignment: Option<&str>,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().expect("lock");
+        let count = conn.execute(
+            "INSERT OR IGNORE INTO packets (hash, status, created_at, updated_at, assignment)
+             VALUES (?1, ?2, ?3, ?3, ?4)",
+            params![hash, status, created_at, assignment],
+        )?;
+        Ok(count != 0)
+    }
+
+    /// Update the status of a packet
+    pub fn set_status(&self, hash: &str, status: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        let count = conn.execute(
+            "UPDATE packets SET status = ?2, updated_at = ?3 WHERE hash = ?1",
+            params![hash, status, now()],
+        )?;
+        if count == 0 {
+            bail!("packet {} not found in metadata store", hash);
+        }
+        Ok(())
+    }
+
+    /// Record the version of the analysis pipeline that produced the result of a packet
+    pub fn set_pipeline(&self, hash: &str, version: u32) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        conn.execute(
+            "UPDATE packets SET pipeline = ?2 WHERE hash = ?1",
+            params![hash, version],
+        )?;
+        Ok(())
+    }
+
+    /// Get the record of a packet
+    pub fn get_packet(&self, hash: &str) -> Result<Option<PacketRecord>> {
+        let conn = self.conn.lock().expect("lock");
+        let record = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM packets WHERE hash = ?1",
+                    PacketRecord::COLUMNS
+                ),
+                params![hash],
+                PacketRecord::from_row,
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    /// List all packets
+    pub fn list_packets(&self) -> Result<Vec<PacketRecord>> {
+        let conn = self.conn.lock().expect("lock");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM packets ORDER BY hash",
+            PacketRecord::COLUMNS
+        ))?;
+        let rows = stmt.query_map([], PacketRecord::from_row)?;
+        let mut records = vec![];
+        for row in rows {
+            records.push(row?);
+        }
+        Ok(records)
+    }
+
+    /// Remove a packet together with its history
+    pub fn remove_packet(&self, hash: &str) -> Result<()> {
+        let mut conn = self.conn.lock().expect("lock");
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM stages WHERE attempt IN (SELECT id FROM attempts WHERE hash = ?1)",
+            params![hash],
+        )?;
+        tx.execute("DELETE FROM attempts WHERE hash = ?1", params![hash])?;
+        tx.execute("DELETE FROM submissions WHERE hash = ?1", params![hash])?;
+        tx.execute("DELETE FROM test_cases WHERE hash = ?1", params![hash])?;
+        tx.execute("DELETE FROM packets WHERE hash = ?1", params![hash])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Record an access to the packet
+    pub fn touch(&self, hash: &str) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        conn.execute(
+            "UPDATE packets SET accessed_at = ?2 WHERE hash = ?1",
+            params![hash, now()],
+        )?;
+        Ok(())
+    }
+
+    /// Mark (or unmark) the artifacts of a packet as evicted
+    pub fn set_evicted(&self, hash: &str, evicted: bool) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        conn.execute(
+            "UPDATE packets SET evicted_at = ?2 WHERE hash = ?1",
+            params![hash, evicted.then(now)],
+        )?;
+        Ok(())
+    }
+
+    /// Record a submission of a packet
+    pub fn add_submission(&self, hash: &str, submitter: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        conn.execute(
+            "INSERT INTO submissions (hash, submitter, submitted_at) VALUES (?1, ?2, ?3)",
+            params![hash, submitter, now()],
+        )?;
+        Ok(())
+    }
+
+    /// List all submissions of a packet, in chronological order
+    pub fn list_submissions(&self, hash: &str) -> Result<Vec<SubmissionRecord>> {
+        let conn = self.conn.lock().expect("lock");
+        let mut stmt = conn.prepare(
+            "SELECT submitter, submitted_at FROM submissions WHERE hash = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![hash], |row| {
+            Ok(SubmissionRecord {
+                submitter: row.get(0)?,
+                submitted_at: row.get(1)?,
+            })
+        })?;
+        let mut records = vec![];
+        for row in rows {
+            records.push(row?);
+        }
+        Ok(records)
+    }
+
+    /// Record the original names of the test cases of a kind (e.g., `input`), in index order
+    pub fn set_test_names(&self, hash: &str, kind: &str, names: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().expect("lock");
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM test_cases WHERE hash = ?1 AND kind = ?2",
+            params![hash, kind],
+        )?;
+        for (i, name) in names.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO test_cases (hash, kind, idx, name) VALUES (?1, ?2, ?3, ?4)",
+                params![hash, kind, i as i64, name],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// List the original names of the test cases of a packet, as kind, index, and name
+    pub fn list_test_names(&self, hash: &str) -> Result<Vec<(String, usize, String)>> {
+        let conn = self.conn.lock().expect("lock");
+        let mut stmt = conn
+            .prepare("SELECT kind, idx, name FROM test_cases WHERE hash = ?1 ORDER BY kind, idx")?;
+        let rows = stmt.query_map(params![hash], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as usize, row.get(2)?))
+        })?;
+        let mut records = vec![];
+        for row in rows {
+            records.push(row?);
+        }
+        Ok(records)
+    }
+
+    /// Start a new analysis attempt on a packet, closing any dangling ones
+    pub fn begin_attempt(&self, hash: &str, worker: &str) -> Result<()> {
+        let mut conn = self.conn.lock().expect("lock");
+        let tx = conn.transaction()?;
+        let ts = now();
+        tx.execute(
+            "UPDATE stages SET finished_at = ?2
+             WHERE finished_at IS NULL
+             AND attempt IN (SELECT id FROM attempts WHERE hash = ?1)",
+            params![hash, ts],
+        )?;
+        tx.execute(
+            "UPDATE attempts SET finished_at = ?2, outcome = 'abandoned'
+             WHERE hash = ?1 AND finished_at IS NULL",
+            params![hash, ts],
+        )?;
+        tx.execute(
+            "INSERT INTO attempts (hash, worker, started_at) VALUES (?1, ?2, ?3)",
+            params![hash, worker, ts],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Close the running stage (if any) of the running attempt of a packet
+    fn close_stage(conn: &Connection, hash: &str) -> Result<()> {
+        let (ts, ts_ms) = (now(), now_ms());
+        conn.execute(
+            "UPDATE stages SET finished_at = ?2, duration_ms = ?3 - started_ms
+             WHERE finished_at IS NULL
+             AND attempt IN (SELECT id FROM attempts WHERE hash = ?1 AND finished_at IS NULL)",
+            params![hash, ts, ts_ms],
+        )?;
+        Ok(())
+    }
+
+    /// Mark the start of a new stage in the running attempt of a packet
+    pub fn begin_stage(&self, hash: &str, stage: &str) -> Result<()> {
+        let mut conn = self.conn.lock().expect("lock");
+        let tx = conn.transaction()?;
+        Self::close_stage(&tx, hash)?;
+        let attempt: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM attempts WHERE hash = ?1 AND finished_at IS NULL
+                 ORDER BY id DESC LIMIT 1",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let attempt = match attempt {
+            None => bail!("no running attempt for packet {}", hash),
+            Some(id) => id,
+        };
+        tx.execute(
+            "INSERT INTO stages (attempt, stage, started_at, started_ms) VALUES (?1, ?2, ?3, ?4)",
+            params![attempt, stage, now(), now_ms()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Finish the running attempt of a packet with an outcome
+    pub fn end_attempt(&self, hash: &str, outcome: &str, message: Option<&str>) -> Result<()> {
+        let mut conn = self.conn.lock().expect("lock");
+        let tx = conn.transaction()?;
+        Self::close_stage(&tx, hash)?;
+        tx.execute(
+            "UPDATE attempts SET finished_at = ?2, outcome = ?3, message = ?4
+             WHERE hash = ?1 AND finished_at IS NULL",
+            params![hash, now(), outcome, message],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Record a finished attempt in one go (used for migration)
+    pub fn add_finished_attempt(
+        &self,
+        hash: &str,
+        finished_at: i64,
+        outcome: &str,
+        message: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().expect("lock");
+        conn.execute(
+            "INSERT INTO attempts (hash, started_at, finished_at, outcome, message)
+             VALUES (?1, ?2, ?2, ?3, ?4)",
+            params![hash, finished_at, outcome, message],
+        )?;
+        Ok(())
+    }
+
+    /// List all analysis attempts of a packet, in chronological order
+    pub fn list_attempts(&self, hash: &str) -> Result<Vec<AttemptRecord>> {
+        let conn = self.conn.lock().expect("lock");
+        let mut stmt = conn.prepare(
+            "SELECT id, worker, started_at, finished_at, outcome, message
+             FROM attempts WHERE hash = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![hash], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                AttemptRecord {
+                    worker: row.get(1)?,
+                    started_at: row.get(2)?,
+                    finished_at: row.get(3)?,
+                    outcome: row.get(4)?,
+                    message: row.get(5)?,
+                    stages: vec![],
+                },
+            ))
+        })?;
+        let mut attempts = vec![];
+        for row in rows {
+            attempts.push(row?);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT stage, started_at, finished_at, duration_ms
+             FROM stages WHERE attempt = ?1 ORDER BY id",
+        )?;
+        let mut records = vec![];
+        for (id, mut attempt) in attempts {
+            let rows = stmt.query_map(params![id], |row| {
+                Ok(StageRecord {
+                    stage: row.get(0)?,
+                    started_at: row.get(1)?,
+                    finished_at: row.get(2)?,
+                    duration_ms: row.get(3)?,
+                })
+            })?;
+            for row in rows {
+                attempt.stages.push(row?);
+            }
+            records.push(attempt);
+        }
+        Ok(records)
+    }
+}
@@ -2,19 +2,69 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
-use std::sync::RwLock;
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 use std::{fs, io};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
+use log::{info, warn};
+use serde_json::json;
 use sha3::{Digest, Sha3_256};
+use tempdir::TempDir;
 
-use crate::process::AnalysisResult;
+use crate::assignment::{Assignment, DIR_ASSIGNMENTS};
+use crate::metadata;
+use crate::metadata::{AttemptRecord, MetaStore, PacketRecord, SubmissionRecord};
+use crate::process::{AnalysisResult, Stage, PIPELINE_VERSION};
+use crate::retention;
+use crate::schema;
+use crate::tool_aflpp;
+use crate::tool_aflpp_stats::LiveStats;
+use crate::util_archive::NamedFiles;
 
 /// Marker for unexpected internal error
-const MARKER_ERROR: &str = "error";
+pub(crate) const MARKER_ERROR: &str = "error";
 
 /// Marker for completed analysis
-const MARKER_RESULT: &str = "result.json";
+pub(crate) const MARKER_RESULT: &str = "result.json";
+
+/// Result of the previous analysis, kept aside when a stale result is retired for comparison
+pub(crate) const FILE_PREVIOUS_RESULT: &str = "result.previous.json";
+
+/// Name of the metadata database file in the registry root
+pub(crate) const FILE_DATABASE: &str = "registry.db";
+
+/// Name of the file recording the assignment a packet is submitted for (absent if none)
+pub(crate) const FILE_ASSIGNMENT: &str = "assignment";
+
+/// Directory next to the registry where packets that cannot be trusted are set aside
+pub(crate) const DIR_QUARANTINE: &str = "quarantine";
+
+/// File in a quarantined packet holding its metadata record and history
+const FILE_HISTORY: &str = "history.json";
+
+/// Extension of files being written
+pub(crate) const SUFFIX_TEMP: &str = "tmp";
+
+/// Optional directory of expected outputs, one per test under `input/` (absent if none)
+pub(crate) const DIR_EXPECTED: &str = "expected";
+
+/// Maximum size of an expected output
+const MAX_EXPECTED_SIZE: u64 = 16 * 1024;
+
+/// Check that a submitter label is sane
+pub fn check_submitter(submitter: &str) -> Result<()> {
+    if submitter.is_empty() || submitter.len() > 64 {
+        bail!("invalid submitter: must be between 1 and 64 characters");
+    }
+    if !submitter
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'))
+    {
+        bail!("invalid submitter: only alphanumeric characters and '.', '_', '-', '@' allowed");
+    }
+    Ok(())
+}
 
 /// Uniquely identifies a packet
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone)]
@@ -23,6 +73,11 @@ pub struct Packet {
 }
 
 impl Packet {
+    /// Refer to a packet by its unique ID
+    pub fn from_id(hash: String) -> Self {
+        Self { hash }
+    }
+
     /// Get the unique ID for this packet
     pub fn id(&self) -> &str {
         &self.hash
@@ -33,67 +88,379 @@ impl Packet {
 #[derive(Copy, Clone)]
 pub enum Status {
     Received,
+    Running,
     Error,
     Completed,
 }
 
+impl Status {
+    /// Representation of the status in the metadata store
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Received => "received",
+            Self::Running => "running",
+            Self::Error => "error",
+            Self::Completed => "completed",
+        }
+    }
+
+    /// Parse the status from its representation in the metadata store
+    pub fn parse(s: &str) -> Result<Self> {
+        let status = match s {
+            "received" => Self::Received,
+            "running" => Self::Running,
+            "error" => Self::Error,
+            "completed" => Self::Completed,
+            _ => bail!("invalid packet status: {}", s),
+        };
+        Ok(status)
+    }
+}
+
+/// Progress of a packet under analysis
+#[derive(Clone)]
+pub struct Progress {
+    pub worker: String,
+    pub started: SystemTime,
+    pub stage: Option<Stage>,
+    pub stage_started: SystemTime,
+    pub stage_budget: Option<Duration>,
+    pub live: Option<LiveStats>,
+}
+
+impl Progress {
+    fn to_human_readable(&self) -> String {
+        let now = SystemTime::now();
+        let elapsed = now.duration_since(self.started).unwrap_or_default();
+        let stage = match self.stage {
+            None => return format!("running on {} for {}", self.worker, fmt_duration(elapsed)),
+            Some(stage) => stage,
+        };
+        let stage_elapsed = now.duration_since(self.stage_started).unwrap_or_default();
+        let mut message = format!(
+            "running on {} for {}, in stage '{}' for {}",
+            self.worker,
+            fmt_duration(elapsed),
+            stage.as_str(),
+            fmt_duration(stage_elapsed)
+        );
+        if let Some(budget) = self.stage_budget {
+            message.push_str(&format!(
+                " ({} remaining)",
+                fmt_duration(budget.saturating_sub(stage_elapsed))
+            ));
+        }
+        if let Some(live) = self.live.as_ref() {
+            message.push_str(&format!("\nfuzzing: {}", live.to_human_readable()));
+        }
+        message
+    }
+}
+
+/// Revision numbers of packets, bumped on every observable change of their progress
+#[derive(Default)]
+struct Revisions {
+    counter: u64,
+    packets: BTreeMap<Packet, u64>,
+}
+
 /// Registry of packets
 pub struct Registry {
     root: RwLock<PathBuf>,
     queue: RwLock<Vec<Packet>>,
-    packets: RwLock<BTreeMap<Packet, Status>>,
+    running: RwLock<BTreeMap<Packet, Progress>>,
+    revisions: Mutex<Revisions>,
+    changed: Condvar,
+    assignments: PathBuf,
+    meta: MetaStore,
 }
 
 impl Registry {
-    /// Create a new registry
-    pub fn new(root: PathBuf) -> Result<Self> {
-        // scan across the root directory
+    /// Open an existing registry without touching the packets in it
+    pub fn open(root: PathBuf) -> Result<Self> {
         if !root.exists() || !root.is_dir() {
             bail!("invalid root path for registry");
         }
 
-        let mut packets = BTreeMap::new();
-        for item in fs::read_dir(&root)? {
-            let item = item?;
-            let hash = item
-                .file_name()
-                .into_string()
-                .map_err(|_| anyhow!("invalid package hash in registry"))?;
+        // open the metadata store and migrate from the directory layout once (the records of
+        // packets whose registration was interrupted are recovered by `fsck --repair`)
+        let meta = MetaStore::open(&root.join(FILE_DATABASE))?;
+        if !meta.is_migrated()? {
+            info!("migrating registry metadata from directory layout");
+            Self::migrate(&root, &meta)?;
+            meta.set_migrated()?;
+        }
 
-            // check packet status
-            let path = item.path();
-            let packet = Packet { hash };
+        let assignments = root.with_file_name(DIR_ASSIGNMENTS);
+        Ok(Self {
+            root: RwLock::new(root),
+            queue: RwLock::new(vec![]),
+            running: RwLock::new(BTreeMap::new()),
+            revisions: Mutex::new(Revisions::default()),
+            changed: Condvar::new(),
+            assignments,
+            meta,
+        })
+    }
 
-            // on completed
-            let path_result = path.join(MARKER_RESULT);
-            if path_result.exists() {
-                packets.insert(packet, Status::Completed);
-                continue;
-            }
+    /// Create a new registry, resetting packets that have not completed analysis
+    pub fn new(root: PathBuf) -> Result<Self> {
+        let registry = Self::open(root)?;
+        let locked = registry.root.read().expect("lock");
+        let root = locked.clone();
+        drop(locked);
 
-            // on error (re-queue the packet for analysis)
-            let path_error = path.join(MARKER_ERROR);
-            if path_error.exists() {
-                fs::remove_file(&path_error)?;
+        let meta = &registry.meta;
+        for record in meta.list_packets()? {
+            match Status::parse(&record.status)? {
+                Status::Completed => continue,
+                Status::Received | Status::Running | Status::Error => (),
             }
+            // a single broken packet should not bring down the whole registry
+            match Self::reset_packet(&root.join(&record.hash)) {
+                Ok(()) => meta.set_status(&record.hash, Status::Received.as_str())?,
+                Err(err) => warn!("unable to reset packet {}: {}", record.hash, err),
+            }
+        }
 
-            // on received or on error and with error cleared
-            eprintln!("Cleaning up {:?}", path);
-            let path_output = path.join("output");
+        Ok(registry)
+    }
+
+    /// Clean up an unfinished packet so that it can be re-queued for analysis
+    fn reset_packet(path: &Path) -> Result<()> {
+        // on error (re-queue the packet for analysis, the error is kept in the history)
+        let path_error = path.join(MARKER_ERROR);
+        if path_error.exists() {
+            let message = fs::read_to_string(&path_error).unwrap_or_default();
+            warn!("re-queueing failed packet {:?}: {}", path, message.trim());
+            fs::remove_file(&path_error)?;
+        }
+
+        // on received or on error and with error cleared
+        info!("cleaning up {:?}", path);
+        let path_hangs = path.join(tool_aflpp::HANGS_DIR);
+        if path_hangs.exists() {
+            fs::remove_dir_all(&path_hangs)?;
+        }
+        let path_output = path.join("output");
+        if tool_aflpp::is_resumable(path) {
+            // keep the fuzzing workspace so that the campaign picks up where it left off
+            info!("keeping the interrupted fuzzing campaign of {:?}", path);
+            for item in fs::read_dir(&path_output)? {
+                let item = item?;
+                if item.file_name() == tool_aflpp::WORKSPACE {
+                    continue;
+                }
+                if item.file_type()?.is_dir() {
+                    fs::remove_dir_all(item.path())?;
+                } else {
+                    fs::remove_file(item.path())?;
+                }
+            }
+            return Ok(());
+        }
+        if path_output.exists() {
             fs::remove_dir_all(&path_output)?;
-            fs::create_dir_all(&path_output)?;
-            packets.insert(packet, Status::Received);
         }
+        fs::create_dir_all(&path_output)?;
+        Ok(())
+    }
 
-        Ok(Self {
-            root: RwLock::new(root),
-            queue: RwLock::new(vec![]),
-            packets: RwLock::new(packets),
-        })
+    /// Derive the records of the packet directories from the directory layout, for the one-shot
+    /// migration to the metadata store (packets recorded by an interrupted run are skipped)
+    fn migrate(root: &Path, meta: &MetaStore) -> Result<()> {
+        for item in fs::read_dir(root)? {
+            let item = item?;
+            if !item.file_type()?.is_dir() {
+                continue;
+            }
+            let hash = match item.file_name().into_string() {
+                Ok(hash) if is_hash(&hash) => hash,
+                Ok(_) => continue,
+                Err(name) => {
+                    warn!("skipping invalid package hash in registry: {:?}", name);
+                    continue;
+                }
+            };
+            if meta.get_packet(&hash)?.is_some() {
+                continue;
+            }
+            match Self::record_from_layout(meta, &hash, &item.path()) {
+                Ok(()) => (),
+                Err(err) => warn!("skipping packet {} in migration: {}", hash, err),
+            }
+        }
+        Ok(())
     }
 
-    /// Register a packet from a filesystem path
-    pub fn register<P: AsRef<Path>>(&self, src: P) -> Result<(Packet, bool)> {
+    /// Derive the metadata record of a packet from its directory layout
+    fn record_from_layout(meta: &MetaStore, hash: &str, path: &Path) -> Result<()> {
+        // use the modification time of the packet as its creation time
+        let created_at = path.metadata()?.mtime();
+        let assignment = read_assignment(path)?;
+        let assignment = assignment.as_deref();
+
+        // on completed
+        let path_result = path.join(MARKER_RESULT);
+        if path_result.exists() {
+            let finished_at = path_result.metadata()?.mtime();
+            meta.add_packet(hash, Status::Completed.as_str(), created_at, assignment)?;
+            meta.add_finished_attempt(hash, finished_at, Status::Completed.as_str(), None)?;
+            return Ok(());
+        }
+
+        // on error
+        let path_error = path.join(MARKER_ERROR);
+        if path_error.exists() {
+            let finished_at = path_error.metadata()?.mtime();
+            let message = fs::read_to_string(&path_error)?;
+            meta.add_packet(hash, Status::Error.as_str(), created_at, assignment)?;
+            meta.add_finished_attempt(hash, finished_at, Status::Error.as_str(), Some(&message))?;
+            return Ok(());
+        }
+
+        // on received
+        meta.add_packet(hash, Status::Received.as_str(), created_at, assignment)?;
+        Ok(())
+    }
+
+    /// Re-create the metadata record of a packet found on disk
+    pub(crate) fn recover_record(&self, packet: &Packet) -> Result<()> {
+        Self::record_from_layout(&self.meta, &packet.hash, &self.packet_path(packet))
+    }
+
+    /// Drop a packet from the metadata store (its directory is left untouched)
+    pub(crate) fn forget(&self, packet: &Packet) -> Result<()> {
+        self.meta.remove_packet(&packet.hash)
+    }
+
+    /// Set a packet aside in the quarantine directory together with its metadata record and
+    /// history, and drop it from the registry, returns where it is moved
+    pub(crate) fn quarantine(&self, packet: &Packet) -> Result<PathBuf> {
+        let locked = self.root.write().expect("lock");
+        let dest = Self::quarantine_dest(&locked, &packet.hash)?;
+
+        let path = locked.join(&packet.hash);
+        if path.exists() {
+            fs::rename(&path, &dest)?;
+        } else {
+            fs::create_dir(&dest)?;
+        }
+        let history = json!({
+            "record": self.meta.get_packet(&packet.hash)?,
+            "submissions": self.meta.list_submissions(&packet.hash)?,
+            "attempts": self.meta.list_attempts(&packet.hash)?,
+            "test_names": self.meta.list_test_names(&packet.hash)?,
+        });
+        fs::write(
+            dest.join(FILE_HISTORY),
+            serde_json::to_string_pretty(&history)?,
+        )?;
+        self.meta.remove_packet(&packet.hash)?;
+        drop(locked);
+        Ok(dest)
+    }
+
+    /// Set an entry of the registry root that is not a packet aside in the quarantine
+    /// directory, returns where it is moved
+    pub(crate) fn quarantine_entry(&self, path: &Path) -> Result<PathBuf> {
+        let locked = self.root.write().expect("lock");
+        let name = match path.file_name() {
+            Some(name) if path.parent() == Some(locked.as_path()) => name.to_string_lossy(),
+            _ => bail!("{:?} is not an entry of the registry root", path),
+        };
+        let dest = Self::quarantine_dest(&locked, &name)?;
+        fs::rename(path, &dest)?;
+        drop(locked);
+        Ok(dest)
+    }
+
+    /// Pick a new path in the quarantine directory next to the registry root
+    fn quarantine_dest(root: &Path, name: &str) -> Result<PathBuf> {
+        let base = root.with_file_name(DIR_QUARANTINE);
+        fs::create_dir_all(&base)?;
+        let dest = base.join(name);
+        if dest.exists() {
+            return Ok(base.join(format!("{}-{}", name, metadata::now())));
+        }
+        Ok(dest)
+    }
+
+    /// Reset a completed packet whose result is unusable so it gets analyzed again
+    pub(crate) fn invalidate_result(&self, packet: &Packet) -> Result<()> {
+        let path = self.packet_path(packet).join(MARKER_RESULT);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        self.meta
+            .set_status(&packet.hash, Status::Received.as_str())
+    }
+
+    /// Retire the result of a packet analyzed by an older version of the pipeline, keeping it
+    /// aside for comparison and resetting the packet to be analyzed again, returns whether the
+    /// result is retired (the caller is responsible for queueing the packet)
+    pub fn retire_outdated(&self, packet: &Packet) -> Result<bool> {
+        // hold the lock so that concurrent submissions do not retire the same result twice
+        let locked = self.root.write().expect("lock");
+        let record = match self.meta.get_packet(&packet.hash)? {
+            None => bail!("packet {} not found in metadata store", packet.hash),
+            Some(record) => record,
+        };
+        if !matches!(Status::parse(&record.status)?, Status::Completed)
+            || record.pipeline.unwrap_or(0) >= PIPELINE_VERSION
+        {
+            return Ok(false);
+        }
+
+        let path = locked.join(&packet.hash);
+        fs::rename(path.join(MARKER_RESULT), path.join(FILE_PREVIOUS_RESULT))?;
+        Self::reset_packet(&path)?;
+        self.meta
+            .set_status(&packet.hash, Status::Received.as_str())?;
+        drop(locked);
+
+        info!(
+            "retired result of packet {} from pipeline version {}",
+            packet.hash,
+            record.pipeline.unwrap_or(0)
+        );
+        Ok(true)
+    }
+
+    /// Root directory of the registry
+    pub fn root(&self) -> PathBuf {
+        let locked = self.root.read().expect("lock");
+        let root = locked.clone();
+        drop(locked);
+        root
+    }
+
+    /// Load the configuration of an assignment (the defaults if `None`)
+    pub fn load_assignment(&self, name: Option<&str>) -> Result<Assignment> {
+        Assignment::load(&self.assignments, name)
+    }
+
+    /// Load the configuration of the assignment a packet is submitted for
+    pub fn assignment(&self, packet: &Packet) -> Result<Assignment> {
+        let record = match self.meta.get_packet(&packet.hash)? {
+            None => bail!("packet {} not found in metadata store", packet.hash),
+            Some(record) => record,
+        };
+        self.load_assignment(record.assignment.as_deref())
+    }
+
+    /// Register a packet from a filesystem path, optionally labelled with its submitter and
+    /// the assignment it is submitted for, also returns whether the packet is known already and
+    /// its analysis is not stale (i.e., it does not need to be queued)
+    pub fn register<P: AsRef<Path>>(
+        &self,
+        src: P,
+        submitter: Option<&str>,
+        assignment: Option<&str>,
+    ) -> Result<(Packet, bool)> {
+        // the assignment must be known
+        self.load_assignment(assignment)?;
+
         let tmp = src.as_ref().canonicalize()?;
         if !tmp.is_dir() {
             bail!("not a directory");
@@ -127,7 +494,7 @@ impl Registry {
             match name.to_str() {
                 None => bail!("unrecognized item: {:?}", name),
                 Some(n) => match n {
-                    "main.c" | "interface.h" | "input" | "crash" => (),
+                    "main.c" | "interface.h" | "input" | "crash" | DIR_EXPECTED => (),
                     _ => {
                         if n.starts_with("README") && ty.is_file() {
                             fs::remove_file(item.path())?;
@@ -148,7 +515,7 @@ impl Registry {
         }
 
         // hasher
-        let mut hasher = Sha3_256::new();
+        let mut hasher = PacketHasher::new();
 
         // program
         let program = base.join("main.c");
@@ -166,11 +533,10 @@ impl Registry {
         perm.set_mode(0o644);
         fs::set_permissions(&program, perm)?;
         // - update hash
-        hasher.update(b"program");
-        let mut file = File::open(&program)?;
-        io::copy(&mut file, &mut hasher)?;
+        hasher.program(&program)?;
 
-        // input tests
+        // input tests (renamed to their indices, with the original names kept in metadata)
+        let mut test_names = BTreeMap::new();
         let mut input_tests = vec![];
         let path_tests = base.join("input");
         if !(path_tests.exists() && path_tests.is_dir()) {
@@ -190,13 +556,10 @@ impl Registry {
             input_tests.push(item_path);
         }
         input_tests.sort();
+        test_names.insert("input", file_names(&input_tests));
         for (i, item_path) in input_tests.into_iter().enumerate() {
             // - hash the input
-            hasher.update(b"input");
-            hasher.update(i.to_ne_bytes());
-            let mut file = File::open(&item_path)?;
-            io::copy(&mut file, &mut hasher)?;
-            drop(file);
+            hasher.test("input", i, &item_path)?;
             // - tweak permission
             let meta = item_path.metadata()?;
             let mut perm = meta.permissions();
@@ -226,13 +589,10 @@ impl Registry {
             input_crash.push(item_path);
         }
         input_crash.sort();
+        test_names.insert("crash", file_names(&input_crash));
         for (i, item_path) in input_crash.into_iter().enumerate() {
             // - hash the input
-            hasher.update(b"crash");
-            hasher.update(i.to_ne_bytes());
-            let mut file = File::open(&item_path)?;
-            io::copy(&mut file, &mut hasher)?;
-            drop(file);
+            hasher.test("crash", i, &item_path)?;
             // - tweak permission
             let meta = item_path.metadata()?;
             let mut perm = meta.permissions();
@@ -242,31 +602,65 @@ impl Registry {
             fs::rename(&item_path, item_path.with_file_name(i.to_string()))?;
         }
 
-        // derive the hash
-        let digest = hasher.finalize();
-        let hash = hex::encode(digest);
+        // expected outputs (optional, named after the input tests and renamed alike)
+        let path_expected = base.join(DIR_EXPECTED);
+        if path_expected.exists() {
+            if !path_expected.is_dir() {
+                bail!("expected/ is invalid");
+            }
+            let mut expected = vec![];
+            for item in fs::read_dir(&path_expected)? {
+                let item = item?;
+                let item_name = item.file_name();
+                if !item.file_type()?.is_file() {
+                    bail!("expected/{:?} is invalid", item_name);
+                }
+                let item_path = item.path();
+                let size = item_path.metadata()?.size();
+                if size > MAX_EXPECTED_SIZE {
+                    bail!("expected/{:?} is too big", item_name);
+                }
+                let index = test_names["input"]
+                    .iter()
+                    .position(|n| item_name.to_str() == Some(n.as_str()));
+                match index {
+                    None => bail!(
+                        "expected/{:?} does not match any test under input/",
+                        item_name
+                    ),
+                    Some(i) => expected.push((i, item_path)),
+                }
+            }
+            expected.sort();
+            for (i, item_path) in expected {
+                // - hash the expected output
+                hasher.test(DIR_EXPECTED, i, &item_path)?;
+                // - tweak permission
+                let meta = item_path.metadata()?;
+                let mut perm = meta.permissions();
+                perm.set_mode(0o644);
+                fs::set_permissions(&item_path, perm)?;
+                // - rename the file after the index of its test
+                fs::rename(&item_path, item_path.with_file_name(i.to_string()))?;
+            }
+        }
 
-        // check for duplication atomically
-        let locked = self.root.write().expect("lock");
-        let root = locked.join(&hash);
-        let existed = root.exists();
-        let err_opt = if existed {
-            None
-        } else {
-            fs::create_dir_all(&root).err()
-        };
-        drop(locked);
-        if let Some(err) = err_opt {
-            bail!(err);
+        // derive the hash (the same program is a different packet for another assignment)
+        if let Some(name) = assignment {
+            hasher.assignment(name);
         }
+        let hash = hasher.finish();
 
-        // prepare the packet in registry
-        if !existed {
-            // copy to destination
-            copy_dir_recursive(base, &root)?;
+        // prepare the packet in a staging directory next to its destination, named so that it
+        // cannot be taken for a packet if left behind
+        let staging = TempDir::new_in(self.root(), &hash)?;
+        let stage = staging.path();
+        {
+            // copy to staging
+            copy_dir_recursive(base, stage)?;
 
             // overwrite the interface file
-            let path = root.join("interface.h");
+            let path = stage.join("interface.h");
             let content = include_bytes!("../asset/interface.h");
             fs::write(&path, content)?;
 
@@ -276,19 +670,116 @@ impl Registry {
             perm.set_mode(0o644);
             fs::set_permissions(&path, perm)?;
 
+            // record the assignment
+            if let Some(name) = assignment {
+                fs::write(stage.join(FILE_ASSIGNMENT), name)?;
+            }
+
             // create an output directory
-            let output = root.join("output");
+            let output = stage.join("output");
             fs::create_dir_all(output)?;
         }
 
+        // check for duplication and move the packet in place together with its record atomically
+        // (a crash in between leaves a directory without record, recovered on the next start)
+        let locked = self.root.write().expect("lock");
+        let root = locked.join(&hash);
+        let existed = root.exists();
+        if !existed {
+            fs::rename(staging.into_path(), &root)?;
+            let recorded = self
+                .meta
+                .add_packet(
+                    &hash,
+                    Status::Received.as_str(),
+                    metadata::now(),
+                    assignment,
+                )
+                .and_then(|_| {
+                    test_names
+                        .iter()
+                        .try_for_each(|(kind, names)| self.meta.set_test_names(&hash, kind, names))
+                });
+            if let Err(err) = recorded {
+                let _ = self.meta.remove_packet(&hash);
+                let _ = fs::remove_dir_all(&root);
+                return Err(err);
+            }
+        }
+        drop(locked);
+        self.meta.add_submission(&hash, submitter)?;
+        self.meta.touch(&hash)?;
+
+        // the cached result only counts if the current pipeline produced it
+        let packet = Packet { hash };
+        let cached = existed && !self.retire_outdated(&packet)?;
+
         // complete the return package
-        Ok((Packet { hash }, existed))
+        Ok((packet, cached))
     }
 
     /// Report a snapshot of all packets the registry accumulates
-    pub fn snapshot(&self) -> BTreeMap<Packet, Status> {
-        let locked = self.packets.read().expect("lock");
-        locked.clone()
+    pub fn snapshot(&self) -> Result<BTreeMap<Packet, Status>> {
+        let mut packets = BTreeMap::new();
+        for record in self.meta.list_packets()? {
+            let status = Status::parse(&record.status)?;
+            packets.insert(Packet { hash: record.hash }, status);
+        }
+        Ok(packets)
+    }
+
+    /// Report the metadata records of all packets
+    pub fn records(&self) -> Result<Vec<PacketRecord>> {
+        self.meta.list_packets()
+    }
+
+    /// Original names of the test cases of a packet
+    pub fn test_names(&self, packet: &Packet) -> Result<TestNames> {
+        let mut names = TestNames::default();
+        for (kind, idx, name) in self.meta.list_test_names(&packet.hash)? {
+            names.0.insert((kind, idx), name);
+        }
+        Ok(names)
+    }
+
+    /// Path to the directory holding the packet
+    pub fn packet_path(&self, packet: &Packet) -> PathBuf {
+        let locked = self.root.read().expect("lock");
+        let path = locked.join(&packet.hash);
+        drop(locked);
+        path
+    }
+
+    /// Evict the bulky artifacts of a packet whose analysis is over, returns the number of bytes
+    /// freed, or `None` if the packet is in flight again (e.g., re-queued by a re-grade)
+    pub fn evict(&self, packet: &Packet) -> Result<Option<u64>> {
+        // hold the lock so that the packet is neither re-queued nor started in the meantime
+        let locked = self.root.write().expect("lock");
+        match self.meta.get_packet(&packet.hash)? {
+            Some(record)
+                if matches!(
+                    Status::parse(&record.status)?,
+                    Status::Completed | Status::Error
+                ) => {}
+            _ => return Ok(None),
+        }
+        let output = locked.join(&packet.hash).join("output");
+        let usage = retention::disk_usage(&output)?;
+        fs::remove_dir_all(&output)?;
+        fs::create_dir_all(&output)?;
+        self.meta.set_evicted(&packet.hash, true)?;
+        drop(locked);
+        Ok(Some(usage))
+    }
+
+    /// Report the submission history of a packet
+    pub fn submissions(&self, packet: &Packet) -> Result<Vec<SubmissionRecord>> {
+        self.meta.list_submissions(&packet.hash)
+    }
+
+    /// Report the analysis history of a packet
+    pub fn attempts(&self, packet: &Packet) -> Result<Vec<AttemptRecord>> {
+        self.meta.list_attempts(&packet.hash)
     }
 
     /// Prepare the workspace
@@ -298,20 +789,36 @@ impl Registry {
         name: &str,
         mnt: &str,
     ) -> Result<DockedPacket> {
+        self.dockerize(pkt, name, mnt, true)
+    }
+
+    /// Prepare the workspace, keeping what an earlier run left in it
+    pub fn reuse_dockerized_packet(
+        &self,
+        pkt: &Packet,
+        name: &str,
+        mnt: &str,
+    ) -> Result<DockedPacket> {
+        self.dockerize(pkt, name, mnt, false)
+    }
+
+    fn dockerize(&self, pkt: &Packet, name: &str, mnt: &str, fresh: bool) -> Result<DockedPacket> {
         let locked = self.root.read().expect("lock");
         let host_base = locked.join(&pkt.hash);
         drop(locked);
 
         // prepare the host workspace path
         let host_output = host_base.join("output").join(name);
-        if host_output.exists() {
+        if host_output.exists() && (fresh || host_output.is_file()) {
             if host_output.is_file() {
                 fs::remove_file(&host_output)?;
             } else {
                 fs::remove_dir_all(&host_output)?;
             }
         }
-        fs::create_dir(&host_output)?;
+        if !host_output.exists() {
+            fs::create_dir(&host_output)?;
+        }
 
         // set the permission
         let mut perms = fs::metadata(&host_output)?.permissions();
@@ -354,14 +861,215 @@ impl Registry {
     }
 
     /// Add the packet to queue
-    pub fn queue(&self, packet: Packet) {
+    pub fn queue(&self, packet: Packet) -> Result<()> {
+        self.meta
+            .set_status(&packet.hash, Status::Received.as_str())?;
+
         let mut locked = self.queue.write().expect("lock");
         locked.push(packet.clone());
         drop(locked);
 
-        let mut locked = self.packets.write().expect("lock");
-        locked.insert(packet, Status::Received);
+        self.bump(&packet);
+        Ok(())
+    }
+
+    /// Mark the start of an analysis on the packet by a worker
+    pub fn begin_analysis(&self, packet: &Packet, worker: &str) -> Result<()> {
+        // under the lock, as an eviction checks the status before wiping the workspace
+        let locked = self.root.read().expect("lock");
+        self.meta.set_evicted(&packet.hash, false)?;
+        self.meta.begin_attempt(&packet.hash, worker)?;
+        self.meta
+            .set_status(&packet.hash, Status::Running.as_str())?;
+        drop(locked);
+
+        // move it from the queue to the running set
+        let mut locked = self.queue.write().expect("lock");
+        locked.retain(|p| p != packet);
+        drop(locked);
+
+        let now = SystemTime::now();
+        let mut locked = self.running.write().expect("lock");
+        locked.insert(
+            packet.clone(),
+            Progress {
+                worker: worker.to_string(),
+                started: now,
+                stage: None,
+                stage_started: now,
+                stage_budget: None,
+                live: None,
+            },
+        );
+        drop(locked);
+
+        self.bump(packet);
+        Ok(())
+    }
+
+    /// Mark the start of an analysis stage on the packet, with an optional time budget
+    pub fn begin_stage(
+        &self,
+        packet: &Packet,
+        stage: Stage,
+        budget: Option<Duration>,
+    ) -> Result<()> {
+        self.meta.begin_stage(&packet.hash, stage.as_str())?;
+
+        let mut locked = self.running.write().expect("lock");
+        if let Some(progress) = locked.get_mut(packet) {
+            progress.stage = Some(stage);
+            progress.stage_started = SystemTime::now();
+            progress.stage_budget = budget;
+            progress.live = None;
+        }
+        drop(locked);
+
+        self.bump(packet);
+        Ok(())
+    }
+
+    /// Publish a snapshot of the fuzzing statistics of a packet under analysis
+    pub fn publish_live(&self, packet: &Packet, live: LiveStats) -> Result<()> {
+        let mut locked = self.running.write().expect("lock");
+        let found = match locked.get_mut(packet) {
+            None => false,
+            Some(progress) => {
+                progress.live = Some(live);
+                true
+            }
+        };
+        drop(locked);
+
+        if !found {
+            bail!("packet {} is not under analysis", packet.hash);
+        }
+        self.bump(packet);
+        Ok(())
+    }
+
+    /// Report the progress of a packet under analysis
+    pub fn progress(&self, packet: &Packet) -> Option<Progress> {
+        let locked = self.running.read().expect("lock");
+        let progress = locked.get(packet).cloned();
+        drop(locked);
+        progress
+    }
+
+    /// Number of packets queued or under analysis
+    pub fn load(&self) -> usize {
+        let locked = self.queue.read().expect("lock");
+        let queued = locked.len();
+        drop(locked);
+
+        let locked = self.running.read().expect("lock");
+        let running = locked.len();
         drop(locked);
+        queued + running
+    }
+
+    /// Remove the packet from both the queue and the running set
+    fn dequeue(&self, packet: &Packet) {
+        let mut locked = self.queue.write().expect("lock");
+        locked.retain(|p| p != packet);
+        drop(locked);
+
+        let mut locked = self.running.write().expect("lock");
+        locked.remove(packet);
+        drop(locked);
+
+        self.bump(packet);
+    }
+
+    /// Record a change on the packet and wake up everyone watching it
+    fn bump(&self, packet: &Packet) {
+        let mut locked = self.revisions.lock().expect("lock");
+        locked.counter += 1;
+        let revision = locked.counter;
+        locked.packets.insert(packet.clone(), revision);
+        drop(locked);
+        self.changed.notify_all();
+    }
+
+    /// Wait until the packet changes past the given revision (or the timeout expires), then
+    /// report its progress as a JSON document; `None` if the packet does not exist
+    pub fn watch_progress(
+        &self,
+        hash: String,
+        since: u64,
+        timeout: Duration,
+    ) -> Result<Option<String>> {
+        let packet = Packet { hash };
+        let record = match self.meta.get_packet(&packet.hash)? {
+            None => return Ok(None),
+            Some(record) => record,
+        };
+
+        // only packets in flight are worth waiting for
+        let status = Status::parse(&record.status)?;
+        let revision = if matches!(status, Status::Received | Status::Running) {
+            let deadline = SystemTime::now() + timeout;
+            let mut locked = self.revisions.lock().expect("lock");
+            loop {
+                let revision = locked.packets.get(&packet).copied().unwrap_or(0);
+                let remaining = deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                if revision > since || remaining.is_zero() {
+                    break revision;
+                }
+                locked = self
+                    .changed
+                    .wait_timeout(locked, remaining)
+                    .expect("lock")
+                    .0;
+            }
+        } else {
+            let locked = self.revisions.lock().expect("lock");
+            let revision = locked.packets.get(&packet).copied().unwrap_or(0);
+            drop(locked);
+            revision
+        };
+
+        // take a fresh look as the status may have changed while waiting
+        let status = match self.meta.get_packet(&packet.hash)? {
+            None => return Ok(None),
+            Some(record) => Status::parse(&record.status)?,
+        };
+        let mut doc = json!({
+            "packet": packet.hash,
+            "revision": revision,
+            "status": status.as_str(),
+        });
+        match status {
+            Status::Received => {
+                let locked = self.queue.read().expect("lock");
+                let index = locked.iter().position(|p| p == &packet);
+                drop(locked);
+                doc["position"] = json!(index);
+            }
+            Status::Running => {
+                if let Some(progress) = self.progress(&packet) {
+                    let now = SystemTime::now();
+                    doc["worker"] = json!(progress.worker);
+                    doc["elapsed"] = json!(now
+                        .duration_since(progress.started)
+                        .unwrap_or_default()
+                        .as_secs());
+                    doc["stage"] = json!(progress.stage.map(|s| s.as_str()));
+                    let stage_elapsed = now
+                        .duration_since(progress.stage_started)
+                        .unwrap_or_default();
+                    doc["stage_elapsed"] = json!(stage_elapsed.as_secs());
+                    doc["stage_remaining"] = json!(progress
+                        .stage_budget
+                        .map(|b| b.saturating_sub(stage_elapsed).as_secs()));
+                    doc["live"] = json!(progress.live);
+                }
+            }
+            Status::Completed | Status::Error => (),
+        }
+        Ok(Some(serde_json::to_string_pretty(&doc)?))
     }
 
     /// Save analysis result
@@ -370,17 +1078,22 @@ impl Registry {
         let locked = self.root.read().expect("lock");
         let path = locked.join(&packet.hash).join(MARKER_RESULT);
         drop(locked);
-        serde_json::to_writer_pretty(File::create(path)?, &result)?;
+
+        // write to a temporary file first so that a crash never leaves a half-written result
+        let path_tmp = path.with_extension(SUFFIX_TEMP);
+        serde_json::to_writer_pretty(File::create(&path_tmp)?, &result)?;
+        fs::rename(&path_tmp, &path)?;
 
         // mark availability
-        let mut locked = self.packets.write().expect("lock");
-        locked.insert(packet.clone(), Status::Completed);
-        drop(locked);
+        self.meta
+            .set_pipeline(&packet.hash, result.pipeline_version)?;
+        self.meta
+            .end_attempt(&packet.hash, Status::Completed.as_str(), None)?;
+        self.meta
+            .set_status(&packet.hash, Status::Completed.as_str())?;
 
         // remove it from queue
-        let mut locked = self.queue.write().expect("lock");
-        locked.retain(|p| p != &packet);
-        drop(locked);
+        self.dequeue(&packet);
 
         // done
         Ok(())
@@ -392,30 +1105,99 @@ impl Registry {
         let locked = self.root.read().expect("lock");
         let path = locked.join(&packet.hash).join(MARKER_ERROR);
         drop(locked);
-        fs::write(path, error)?;
+        fs::write(path, &error)?;
 
         // mark availability
-        let mut locked = self.packets.write().expect("lock");
-        locked.insert(packet.clone(), Status::Error);
-        drop(locked);
+        self.meta
+            .end_attempt(&packet.hash, Status::Error.as_str(), Some(&error))?;
+        self.meta.set_status(&packet.hash, Status::Error.as_str())?;
 
         // remove it from queue
-        let mut locked = self.queue.write().expect("lock");
-        locked.retain(|p| p != &packet);
-        drop(locked);
+        self.dequeue(&packet);
 
         // done
         Ok(())
     }
 
-    /// Load analysis result or error
-    pub fn load_packet_status(&self, hash: String) -> Result<Option<String>> {
+    /// Read the analysis result file of a packet
+    pub(crate) fn read_result(&self, packet: &Packet) -> Result<AnalysisResult> {
+        Self::read_result_at(&self.packet_path(packet))
+    }
+
+    /// Read the analysis result file in a packet directory
+    fn read_result_at(path: &Path) -> Result<AnalysisResult> {
+        let path = path.join(MARKER_RESULT);
+        if !path.exists() {
+            bail!("unable to find analysis result file");
+        }
+        schema::load(&path)
+    }
+
+    /// Read the result of the analysis before the last re-grade of a packet, if any
+    pub(crate) fn read_previous_result(&self, packet: &Packet) -> Result<Option<AnalysisResult>> {
+        let path = self.packet_path(packet).join(FILE_PREVIOUS_RESULT);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(schema::load(&path)?))
+    }
+
+    /// Load the analysis result of a packet, `None` if the packet has not completed analysis
+    pub fn load_result(&self, hash: String) -> Result<Option<AnalysisResult>> {
+        let packet = Packet { hash };
+
+        // the status and the result are read together, as outdated results are retired under
+        // the same lock
+        let locked = self.root.read().expect("lock");
+        let status = match self.meta.get_packet(&packet.hash)? {
+            None => return Ok(None),
+            Some(record) => Status::parse(&record.status)?,
+        };
+        if !matches!(status, Status::Completed) {
+            return Ok(None);
+        }
+        let result = Self::read_result_at(&locked.join(&packet.hash))?;
+        drop(locked);
+
+        self.meta.touch(&packet.hash)?;
+        Ok(Some(result))
+    }
+
+    /// Load the hanging inputs found by fuzzing a packet as file name and content, `None` if the
+    /// packet has not completed analysis
+    pub fn load_hangs(&self, hash: String) -> Result<Option<NamedFiles>> {
+        let packet = Packet { hash };
+        let locked = self.root.read().expect("lock");
+        match self.meta.get_packet(&packet.hash)? {
+            Some(record) if matches!(Status::parse(&record.status)?, Status::Completed) => (),
+            _ => return Ok(None),
+        }
+        let hangs = tool_aflpp::hang_inputs(&locked.join(&packet.hash))?;
+        drop(locked);
+
+        self.meta.touch(&packet.hash)?;
+        Ok(Some(hangs))
+    }
+
+    /// Load analysis result or error, with the parts reserved for the staff if `staff` is set
+    pub fn load_packet_status(&self, hash: String, staff: bool) -> Result<Option<String>> {
         let packet = Packet { hash };
 
-        // check availability
-        let locked = self.packets.read().expect("lock");
-        let status = locked.get(&packet).cloned();
+        // check availability, reading the result along with the status as a re-submission may
+        // retire an outdated result (under the same lock) right after its status is read
+        let locked = self.root.read().expect("lock");
+        let status = match self.meta.get_packet(&packet.hash)? {
+            None => None,
+            Some(record) => Some(Status::parse(&record.status)?),
+        };
+        let result = match status {
+            Some(Status::Completed) => Some(Self::read_result_at(&locked.join(&packet.hash))?),
+            _ => None,
+        };
         drop(locked);
+        if status.is_some() {
+            self.meta.touch(&packet.hash)?;
+        }
 
         let message = match status {
             None => None,
@@ -424,21 +1206,24 @@ impl Registry {
                 let index = locked.iter().position(|p| p == &packet);
                 drop(locked);
                 match index {
-                    None => {
-                        bail!("unable to find packet in queue");
-                    }
+                    // e.g., reset for a re-grade and not yet handed back to the queue
+                    None => Some("queued for analysis".to_string()),
                     Some(pos) => Some(format!("queued at position {}", pos)),
                 }
             }
+            Some(Status::Running) => match self.progress(&packet) {
+                None => {
+                    bail!("unable to find packet in running set");
+                }
+                Some(progress) => Some(progress.to_human_readable()),
+            },
             Some(Status::Completed) => {
-                let locked = self.root.read().expect("lock");
-                let path = locked.join(&packet.hash).join(MARKER_RESULT);
-                drop(locked);
-                if !path.exists() {
-                    bail!("unable to find analysis result file");
+                let result = result.expect("result of completed packet");
+                if staff {
+                    Some(result.to_human_readable_for_staff())
+                } else {
+                    Some(result.to_human_readable())
                 }
-                let result: AnalysisResult = serde_json::from_reader(File::open(path)?)?;
-                Some(result.to_human_readable())
             }
             Some(Status::Error) => {
                 let locked = self.root.read().expect("lock");
@@ -455,6 +1240,137 @@ impl Registry {
     }
 }
 
+/// Format a duration in a human-readable way
+pub(crate) fn fmt_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+/// Incremental hashing of packet content, shared by registration and integrity checks
+struct PacketHasher(Sha3_256);
+
+impl PacketHasher {
+    fn new() -> Self {
+        Self(Sha3_256::new())
+    }
+
+    /// Hash the program file
+    fn program(&mut self, path: &Path) -> io::Result<()> {
+        self.0.update(b"program");
+        io::copy(&mut File::open(path)?, &mut self.0)?;
+        Ok(())
+    }
+
+    /// Hash a test case at its index within the given kind of tests
+    fn test(&mut self, kind: &str, index: usize, path: &Path) -> io::Result<()> {
+        self.0.update(kind.as_bytes());
+        self.0.update(index.to_ne_bytes());
+        io::copy(&mut File::open(path)?, &mut self.0)?;
+        Ok(())
+    }
+
+    /// Hash the assignment the packet is submitted for
+    fn assignment(&mut self, name: &str) {
+        self.0.update(b"assignment");
+        self.0.update(name.as_bytes());
+    }
+
+    fn finish(self) -> String {
+        hex::encode(self.0.finalize())
+    }
+}
+
+/// Re-derive the hash of a packet already stored in the registry
+pub(crate) fn hash_stored(base: &Path) -> Result<String> {
+    let mut hasher = PacketHasher::new();
+    hasher.program(&base.join("main.c"))?;
+    for kind in ["input", "crash"] {
+        let dir = base.join(kind);
+        let count = fs::read_dir(&dir)?.count();
+        for i in 0..count {
+            let path = dir.join(i.to_string());
+            if !path.is_file() {
+                bail!("{}/{} is missing", kind, i);
+            }
+            hasher.test(kind, i, &path)?;
+        }
+    }
+    let dir = base.join(DIR_EXPECTED);
+    if dir.is_dir() {
+        let mut indices = vec![];
+        for item in fs::read_dir(&dir)? {
+            let item = item?;
+            match item
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                None => bail!("{}/{:?} is invalid", DIR_EXPECTED, item.file_name()),
+                Some(i) => indices.push(i),
+            }
+        }
+        indices.sort();
+        for i in indices {
+            hasher.test(DIR_EXPECTED, i, &dir.join(i.to_string()))?;
+        }
+    }
+    if let Some(name) = read_assignment(base)? {
+        hasher.assignment(&name);
+    }
+    Ok(hasher.finish())
+}
+
+/// Read the assignment recorded in a packet directory, if any
+fn read_assignment(base: &Path) -> Result<Option<String>> {
+    let path = base.join(FILE_ASSIGNMENT);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let name = fs::read_to_string(path)?;
+    Assignment::check_name(&name)?;
+    Ok(Some(name))
+}
+
+/// Original names of the test cases in a packet, which are stored under their indices
+#[derive(Default)]
+pub struct TestNames(BTreeMap<(String, usize), String>);
+
+impl TestNames {
+    /// Original name of a stored test case given its path (e.g., `/test/input/3`), falling back
+    /// to the stored name for packets registered without the names
+    pub fn lookup(&self, path: &str) -> String {
+        let path = Path::new(path);
+        let stored = path
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string());
+        let kind = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string());
+        stored
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| self.0.get(&(kind, idx)))
+            .cloned()
+            .unwrap_or(stored)
+    }
+}
+
+/// Names of files, in the given order
+fn file_names(paths: &[PathBuf]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|p| {
+            p.file_name()
+                .map_or_else(String::new, |n| n.to_string_lossy().to_string())
+        })
+        .collect()
+}
+
 /// Dockerized packet
 pub struct DockedPacket {
     pub host_base: PathBuf,
@@ -484,7 +1400,21 @@ fn path_to_str(path: PathBuf) -> String {
     path.into_os_string().into_string().expect("ascii path")
 }
 
-fn copy_dir_recursive(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+/// Check whether a name looks like a packet hash (hex-encoded SHA3-256)
+pub(crate) fn is_hash(name: &str) -> bool {
+    name.len() == 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Check whether a name is the one of a staging directory of a registration in progress, i.e.,
+/// `<hash>.<random>`
+pub(crate) fn is_staging(name: &str) -> bool {
+    name.split_once('.').is_some_and(|(hash, _)| is_hash(hash))
+}
+
+pub(crate) fn copy_dir_recursive(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
     fs::create_dir_all(&dst)?;
     for entry in fs::read_dir(src)? {
         let entry = entry?;
@@ -497,3 +1427,48 @@ fn copy_dir_recursive(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Resul
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a package in a directory, with expected outputs if asked for
+    fn write_package(dir: &Path, expected: bool) {
+        fs::create_dir_all(dir.join("input")).unwrap();
+        fs::create_dir_all(dir.join("crash")).unwrap();
+        fs::write(dir.join("main.c"), "int main() { return 0; }\n").unwrap();
+        fs::write(dir.join("input").join("hello"), "hello\n").unwrap();
+        fs::write(dir.join("input").join("world"), "world\n").unwrap();
+        fs::write(dir.join("crash").join("boom"), "boom\n").unwrap();
+        if expected {
+            fs::create_dir_all(dir.join(DIR_EXPECTED)).unwrap();
+            fs::write(dir.join(DIR_EXPECTED).join("world"), "world\n").unwrap();
+        }
+    }
+
+    #[test]
+    fn stored_packet_hashes_to_its_id() {
+        let tmp = TempDir::new("pap-test").unwrap();
+        let root = tmp.path().join("data");
+        fs::create_dir(&root).unwrap();
+        fs::create_dir(tmp.path().join(DIR_ASSIGNMENTS)).unwrap();
+        fs::write(tmp.path().join(DIR_ASSIGNMENTS).join("a1.json"), "{}").unwrap();
+        let registry = Registry::open(root).unwrap();
+
+        let mut ids = BTreeSet::new();
+        for expected in [false, true] {
+            for assignment in [None, Some("a1")] {
+                let src = TempDir::new("pap-test-src").unwrap();
+                write_package(src.path(), expected);
+                let (packet, cached) = registry
+                    .register(src.path(), Some("alice"), assignment)
+                    .unwrap();
+                assert!(!cached);
+                let stored = hash_stored(&registry.packet_path(&packet)).unwrap();
+                assert_eq!(stored, packet.id());
+                ids.insert(packet.id().to_string());
+            }
+        }
+        assert_eq!(ids.len(), 4);
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use crossbeam_channel::{Receiver, Sender};
+use log::{error, info};
+
+use crate::cores::CorePool;
+use crate::packet::{Packet, Registry, Status};
+use crate::process::analyze;
+use crate::retention;
+use crate::util_docker::Dock;
+
+/// Interval between two checks on the progress of the packets analyzed in one go
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Spawn workers that analyze the packets received from the channel
+pub fn spawn_workers(
+    registry: &'static Registry,
+    cores: &'static CorePool,
+    channel: &Receiver<Packet>,
+    count: usize,
+) -> Vec<JoinHandle<()>> {
+    let mut handles = Vec::with_capacity(count);
+    for i in 0..count {
+        let c_recv = channel.clone();
+        let handle = thread::spawn(move || {
+            // init docker
+            let dock = Dock::new(format!("worker-{}", i)).expect("docker");
+
+            loop {
+                // wait for packet
+                let packet = match c_recv.recv() {
+                    Ok(pkt) => pkt,
+                    Err(err) => {
+                        error!(
+                            "[worker {}] unexpected error when receiving packets: {}",
+                            i, err
+                        );
+                        continue;
+                    }
+                };
+                let hash = packet.id().to_string();
+                info!("[worker {}] received packet: {}", i, hash);
+                match registry.begin_analysis(&packet, &format!("worker-{}", i)) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        error!("[worker {}] failed to record analysis start: {}", i, e);
+                    }
+                }
+
+                // process the packet
+                match analyze(&dock, registry, cores, &packet) {
+                    Ok(result) => {
+                        match registry.save_result(packet.clone(), result) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("[worker {}] failed to save analysis result: {}", i, e);
+                            }
+                        };
+                        info!("[worker {}] packet analyzed: {}", i, hash);
+
+                        // shrink the artifacts no longer needed
+                        match retention::compact(registry, &packet) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("[worker {}] failed to compact artifacts: {}", i, e);
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        error!(
+                            "[worker {}] unexpected error when analyzing packet: {}",
+                            i, err
+                        );
+                        match registry.save_error(packet, err.to_string()) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("[worker {}] failed to save analysis error: {}", i, e);
+                            }
+                        };
+                    }
+                }
+            }
+        });
+        handles.push(handle);
+    }
+    handles
+}
+
+/// Queue the packets that are yet to be analyzed and wait until all of them are, reporting the
+/// progress along the way under the given tag (e.g., `batch`)
+pub fn analyze_all(
+    registry: &Registry,
+    channel: &Sender<Packet>,
+    workers: &[JoinHandle<()>],
+    packets: &BTreeSet<Packet>,
+    tag: &str,
+) -> Result<()> {
+    let snapshot = registry.snapshot()?;
+    for packet in packets {
+        if matches!(snapshot.get(packet), Some(Status::Received)) {
+            registry.queue(packet.clone())?;
+            channel.send(packet.clone())?;
+        }
+    }
+
+    let mut reported = None;
+    loop {
+        let snapshot = registry.snapshot()?;
+        let done = packets
+            .iter()
+            .filter(|p| matches!(snapshot.get(p), Some(Status::Completed | Status::Error)))
+            .count();
+        if reported != Some(done) {
+            info!(
+                "[{}] {} out of {} packet(s) analyzed, {} in the queue",
+                tag,
+                done,
+                packets.len(),
+                registry.load()
+            );
+            reported = Some(done);
+        }
+        if done == packets.len() {
+            return Ok(());
+        }
+        if workers.iter().all(|h| h.is_finished()) {
+            bail!("all workers terminated before the {} is analyzed", tag);
+        }
+        thread::sleep(PROGRESS_INTERVAL);
+    }
+}
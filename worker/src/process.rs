@@ -1,12 +1,27 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use crate::cores::CorePool;
 use crate::packet::{Packet, Registry};
-use crate::tool_aflpp::{run_aflpp, ResultAFLpp};
-use crate::tool_gcov::{run_baseline, run_gcov, ResultBaseline, ResultGcov};
+use crate::rubric::RubricScore;
+use crate::schema::RESULT_SCHEMA_VERSION;
+use crate::tool_aflpp::{run_aflpp, Checkpoint, ResultAFLpp};
+use crate::tool_differential::{run_differential, ResultDifferential};
+use crate::tool_gcov::{
+    run_baseline, run_gcov, run_hidden, ResultBaseline, ResultGcov, ResultHidden,
+};
+use crate::tool_mutation::{run_mutation, ResultMutation};
+use crate::tool_sanitizer::{run_sanitizers, ResultSanitizer};
 use crate::util_docker::Dock;
 use crate::{tool_aflpp, tool_gcov};
 
+/// Version of the analysis pipeline, to be bumped on every change that may affect the results
+/// (e.g., a fix in a grader or an upgrade of a tool); cached results of an older version are
+/// stale and get analyzed again on resubmission or by a re-grade
+pub const PIPELINE_VERSION: u32 = 6;
+
 /// Provision all the tools
 pub fn provision(force: bool) -> Result<()> {
     let dock = Dock::new("provision".to_string())?;
@@ -15,40 +30,231 @@ pub fn provision(force: bool) -> Result<()> {
     Ok(())
 }
 
+/// Stages of the analysis pipeline
+#[derive(Copy, Clone)]
+pub enum Stage {
+    Baseline,
+    Hidden,
+    Sanitizer,
+    Gcov,
+    Mutation,
+    AFLpp,
+    Differential,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Baseline => "baseline",
+            Self::Hidden => "hidden",
+            Self::Sanitizer => "sanitizer",
+            Self::Gcov => "gcov",
+            Self::Mutation => "mutation",
+            Self::AFLpp => "aflpp",
+            Self::Differential => "differential",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct AnalysisResult {
-    result_baseline: ResultBaseline,
-    result_gcov: ResultGcov,
-    result_aflpp: ResultAFLpp,
+    /// version of the schema the result is stored in, `0` if it predates versioning
+    #[serde(default)]
+    pub(crate) schema_version: u32,
+    /// version of the pipeline producing the result, `0` if it predates versioning
+    #[serde(default)]
+    pub(crate) pipeline_version: u32,
+    #[serde(default)]
+    pub(crate) assignment: Option<String>,
+    pub(crate) result_baseline: ResultBaseline,
+    #[serde(default)]
+    pub(crate) result_hidden: Option<ResultHidden>,
+    #[serde(default)]
+    pub(crate) result_sanitizer: Option<ResultSanitizer>,
+    pub(crate) result_gcov: ResultGcov,
+    #[serde(default)]
+    pub(crate) result_mutation: Option<ResultMutation>,
+    pub(crate) result_aflpp: ResultAFLpp,
+    #[serde(default)]
+    pub(crate) result_differential: Option<ResultDifferential>,
+    /// score under the rubric of the assignment, if it has one
+    #[serde(default)]
+    pub(crate) rubric: Option<RubricScore>,
 }
 
 impl AnalysisResult {
+    /// Report shown to students, with the hidden tests only summarized
     pub fn to_human_readable(&self) -> String {
-        vec![
+        self.render(false)
+    }
+
+    /// Report shown to the staff, with the hidden tests in full
+    pub fn to_human_readable_for_staff(&self) -> String {
+        self.render(true)
+    }
+
+    fn render(&self, staff: bool) -> String {
+        let header = match self.assignment.as_ref() {
+            None => String::new(),
+            Some(name) => format!("assignment: {}\n\n", name),
+        };
+        let mut sections = vec![];
+        if let Some(score) = self.rubric.as_ref() {
+            sections.extend([
+                "==== Rubric ====".to_string(),
+                score.to_human_readable(),
+                String::new(),
+            ]);
+        }
+        sections.extend([
             "==== Baseline ====".to_string(),
             self.result_baseline.to_human_readable(),
             String::new(),
+        ]);
+        if let Some(result) = self.result_hidden.as_ref() {
+            sections.extend([
+                "==== Hidden Tests ====".to_string(),
+                if staff {
+                    result.to_human_readable()
+                } else {
+                    result.to_summary()
+                },
+                String::new(),
+            ]);
+        }
+        if let Some(result) = self.result_sanitizer.as_ref() {
+            sections.extend([
+                "==== Sanitizers ====".to_string(),
+                result.to_human_readable(),
+                String::new(),
+            ]);
+        }
+        sections.extend([
             "==== GCOV ====".to_string(),
             self.result_gcov.to_human_readable(),
             String::new(),
+        ]);
+        if let Some(result) = self.result_mutation.as_ref() {
+            sections.extend([
+                "==== Mutation ====".to_string(),
+                result.to_human_readable(),
+                String::new(),
+            ]);
+        }
+        sections.extend([
             "==== AFL++ ====".to_string(),
             self.result_aflpp.to_human_readable(),
             String::new(),
-        ]
-        .join("\n")
+        ]);
+        if let Some(result) = self.result_differential.as_ref() {
+            sections.extend([
+                "==== Differential ====".to_string(),
+                result.to_human_readable(),
+                String::new(),
+            ]);
+        }
+        header + &sections.join("\n")
+    }
+
+    /// Coverage-over-time series of the AFL++ campaign in CSV, if available
+    pub fn aflpp_series_csv(&self) -> Option<String> {
+        self.result_aflpp
+            .metrics
+            .as_ref()
+            .map(|metrics| metrics.series_to_csv())
     }
 }
 
 /// Analyze a packet
-pub fn analyze(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<AnalysisResult> {
-    let result_baseline = run_baseline(dock, registry, packet)?;
+pub fn analyze(
+    dock: &Dock,
+    registry: &Registry,
+    cores: &CorePool,
+    packet: &Packet,
+) -> Result<AnalysisResult> {
+    let assignment = registry.assignment(packet)?;
+
+    registry.begin_stage(packet, Stage::Baseline, None)?;
+    let result_baseline = run_baseline(dock, registry, packet, &assignment)?;
+    let result_hidden = match assignment.hidden_suite()? {
+        None => None,
+        Some(suite) => {
+            registry.begin_stage(packet, Stage::Hidden, None)?;
+            Some(run_hidden(dock, registry, packet, &assignment, &suite)?)
+        }
+    };
+    let result_sanitizer = if assignment.sanitizer.sanitizers.is_empty() {
+        None
+    } else {
+        registry.begin_stage(packet, Stage::Sanitizer, None)?;
+        Some(run_sanitizers(
+            dock,
+            registry,
+            packet,
+            &assignment.sanitizer,
+        )?)
+    };
+    registry.begin_stage(packet, Stage::Gcov, None)?;
     let result_gcov = run_gcov(dock, registry, packet)?;
-    let result_aflpp = run_aflpp(dock, registry, packet)?;
+    let result_mutation = if assignment.mutation.max_mutants == 0 {
+        None
+    } else {
+        registry.begin_stage(packet, Stage::Mutation, None)?;
+        Some(run_mutation(dock, registry, packet, &assignment)?)
+    };
+
+    // fuzzing gets dedicated cores, wait for them before its clock starts;
+    // an interrupted campaign asks for as many cores as it had and only for the time left
+    let checkpoint = Checkpoint::load(registry, packet);
+    let instances = checkpoint.as_ref().map_or_else(
+        || tool_aflpp::plan_instances(registry, cores.total()),
+        |c| c.instances,
+    );
+    let spent = checkpoint.map_or(Duration::ZERO, |c| c.wall_clock());
+    let reservation = cores.reserve(instances);
+    registry.begin_stage(
+        packet,
+        Stage::AFLpp,
+        Some(
+            assignment
+                .aflpp
+                .budget
+                .wall_clock_limit()
+                .saturating_sub(spent),
+        ),
+    )?;
+    let result_aflpp = run_aflpp(dock, registry, packet, &assignment, reservation.cores())?;
+    drop(reservation);
+
+    // replaying the fuzzing queue needs the campaign to be over
+    let result_differential = match assignment.reference.as_ref() {
+        None => None,
+        Some(reference) => {
+            registry.begin_stage(packet, Stage::Differential, None)?;
+            Some(run_differential(
+                dock,
+                registry,
+                packet,
+                &assignment,
+                &assignment.resolve(reference)?,
+            )?)
+        }
+    };
 
-    // collect and dump result
-    Ok(AnalysisResult {
+    // collect and score the result
+    let mut result = AnalysisResult {
+        schema_version: RESULT_SCHEMA_VERSION,
+        pipeline_version: PIPELINE_VERSION,
+        assignment: assignment.name().map(|s| s.to_string()),
         result_baseline,
+        result_hidden,
+        result_sanitizer,
         result_gcov,
+        result_mutation,
         result_aflpp,
-    })
+        result_differential,
+        rubric: None,
+    };
+    result.rubric = assignment.rubric.grade(&result);
+    Ok(result)
 }
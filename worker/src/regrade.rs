@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use log::info;
+use serde::Serialize;
+
+use crate::gradebook::{csv_field, Format};
+use crate::packet::{Packet, Registry, Status};
+use crate::process::PIPELINE_VERSION;
+use crate::rubric::Metric;
+use crate::util_diff::{compare, CompareMode};
+
+/// A packet whose result is retired for a re-grade
+pub struct Retired {
+    pub packet: Packet,
+    /// version of the pipeline that produced the retired result
+    pub version: u32,
+}
+
+/// Retire the results of all packets analyzed by an older version of the pipeline, optionally
+/// restricted to an assignment, so that they get analyzed again
+pub fn retire(registry: &Registry, assignment: Option<&str>) -> Result<Vec<Retired>> {
+    let mut retired = vec![];
+    for record in registry.records()? {
+        if assignment.is_some() && record.assignment.as_deref() != assignment {
+            continue;
+        }
+        let packet = Packet::from_id(record.hash);
+        let version = record.pipeline.unwrap_or(0);
+
+        // results retired before but not analyzed again yet (e.g., on a resubmission or by an
+        // interrupted re-grade) are picked up as well
+        let pending = matches!(Status::parse(&record.status)?, Status::Received)
+            && version < PIPELINE_VERSION
+            && registry.read_previous_result(&packet)?.is_some();
+        if pending || registry.retire_outdated(&packet)? {
+            retired.push(Retired { packet, version });
+        }
+    }
+    info!(
+        "[regrade] {} packet(s) analyzed before pipeline version {}",
+        retired.len(),
+        PIPELINE_VERSION
+    );
+    Ok(retired)
+}
+
+/// A packet whose result changed in the re-grade
+#[derive(Serialize)]
+pub struct Change {
+    pub packet: String,
+    pub assignment: Option<String>,
+    /// everyone who submitted the packet
+    pub submitters: Vec<String>,
+    /// version of the pipeline that produced the previous result
+    pub version_before: u32,
+    /// status of the re-analysis
+    pub status: String,
+    pub score_before: Option<f64>,
+    pub score_after: Option<f64>,
+    /// metrics whose value changed, by name, as the values before and after (only compared if
+    /// the re-analysis completed)
+    pub metrics: BTreeMap<&'static str, (Option<f64>, Option<f64>)>,
+    /// diff of the staff reports, abridged
+    pub report_diff: Option<String>,
+}
+
+/// Compare the results of the re-graded packets against the retired ones, reporting only those
+/// whose status, score, or metrics changed (the reports alone are not compared, as fuzzing is
+/// not reproducible across runs)
+pub fn report(registry: &Registry, retired: &[Retired]) -> Result<Vec<Change>> {
+    let snapshot = registry.snapshot()?;
+    let mut changes = vec![];
+    for item in retired {
+        let before = match registry.read_previous_result(&item.packet)? {
+            None => continue,
+            Some(result) => result,
+        };
+        let status = snapshot.get(&item.packet).copied().unwrap_or(Status::Error);
+        let after = match status {
+            Status::Completed => Some(registry.read_result(&item.packet)?),
+            Status::Received | Status::Running | Status::Error => None,
+        };
+
+        let score_before = before.rubric.as_ref().map(|s| s.score);
+        let score_after = after
+            .as_ref()
+            .and_then(|r| r.rubric.as_ref())
+            .map(|s| s.score);
+        let metrics: BTreeMap<_, _> = match after.as_ref() {
+            None => BTreeMap::new(),
+            Some(after) => Metric::ALL
+                .iter()
+                .map(|m| (m.as_str(), (m.evaluate(&before), m.evaluate(after))))
+                .filter(|(_, (v1, v2))| v1 != v2)
+                .collect(),
+        };
+        if after.is_some() && score_before == score_after && metrics.is_empty() {
+            continue;
+        }
+
+        let report_diff = after.as_ref().and_then(|after| {
+            compare(
+                CompareMode::Exact,
+                &before.to_human_readable_for_staff(),
+                &after.to_human_readable_for_staff(),
+            )
+        });
+        let submitters = registry
+            .submissions(&item.packet)?
+            .into_iter()
+            .filter_map(|s| s.submitter)
+            .collect();
+        changes.push(Change {
+            packet: item.packet.id().to_string(),
+            assignment: before.assignment.clone(),
+            submitters,
+            version_before: item.version,
+            status: status.as_str().to_string(),
+            score_before,
+            score_after,
+            metrics,
+            report_diff,
+        });
+    }
+    Ok(changes)
+}
+
+/// Render the changes in the given format, the CSV one without the diff of the reports
+pub fn render(changes: &[Change], format: Format) -> Result<String> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(changes)?),
+        Format::Csv => {
+            let number = |v: Option<f64>| v.map_or_else(String::new, |v| format!("{:.2}", v));
+            let mut lines = vec![
+                "packet,assignment,submitters,version_before,status,score_before,score_after,metrics"
+                    .to_string(),
+            ];
+            for change in changes {
+                let metrics: Vec<_> = change
+                    .metrics
+                    .iter()
+                    .map(|(name, (v1, v2))| format!("{}: {} -> {}", name, number(*v1), number(*v2)))
+                    .collect();
+                let row = [
+                    change.packet.clone(),
+                    change.assignment.clone().unwrap_or_default(),
+                    change.submitters.join(" "),
+                    change.version_before.to_string(),
+                    change.status.clone(),
+                    number(change.score_before),
+                    number(change.score_after),
+                    metrics.join("; "),
+                ];
+                let row: Vec<_> = row.iter().map(|field| csv_field(field)).collect();
+                lines.push(row.join(","));
+            }
+            lines.push(String::new());
+            Ok(lines.join("\n"))
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs;
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use tar::{Builder, HeaderMode};
+
+use crate::packet::{Packet, Registry, Status};
+
+/// Default global disk quota for all packets in the registry
+pub const DEFAULT_DISK_QUOTA: u64 = 32 * 1024 * 1024 * 1024;
+
+/// Names of compiled binaries in each tool workspace
+const COMPILED_BINARIES: &[&str] = &[
+    "main",
+    "main.cmplog",
+    "main.address",
+    "main.undefined",
+    "main.memory",
+    "reference",
+];
+
+/// AFL++ instance content that is bulky and only needed during fuzzing
+const AFLPP_SCRATCH: &[&str] = &[".cur_input", ".synced", "cmdline", "is_main_node"];
+
+/// AFL++ instance content that is bulky but worth keeping in compressed form
+const AFLPP_ARCHIVED: &[&str] = &["queue"];
+
+/// Summary of a garbage collection round
+pub struct GcReport {
+    pub total_before: u64,
+    pub total_after: u64,
+    pub evicted: Vec<Packet>,
+}
+
+/// Disk usage of a path (recursively for a directory), in bytes
+pub fn disk_usage(path: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.blocks() * 512);
+    }
+    let mut total = meta.blocks() * 512;
+    for item in fs::read_dir(path)? {
+        total += disk_usage(&item?.path())?;
+    }
+    Ok(total)
+}
+
+/// Remove a path regardless of whether it is a file or a directory
+fn remove_path(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Pack a directory into a tar.gz archive next to it and remove the directory
+fn archive_dir(path: &Path) -> Result<()> {
+    let mut name = path.file_name().expect("file name").to_os_string();
+    name.push(".tar.gz");
+    let dst = path.with_file_name(name);
+
+    let encoder = GzEncoder::new(File::create(&dst)?, Compression::best());
+    let mut tarball = Builder::new(encoder);
+    tarball.follow_symlinks(false);
+    tarball.mode(HeaderMode::Deterministic);
+    tarball.append_dir_all(".", path)?;
+    tarball.into_inner()?.finish()?;
+
+    fs::remove_dir_all(path)?;
+    Ok(())
+}
+
+/// Compact the artifacts of an analyzed packet, keeping only what results and downloads need
+pub fn compact(registry: &Registry, packet: &Packet) -> Result<()> {
+    let output = registry.packet_path(packet).join("output");
+    if !output.exists() {
+        return Ok(());
+    }
+    let before = disk_usage(&output)?;
+
+    // compiled binaries can always be re-built
+    for item in fs::read_dir(&output)? {
+        let workspace = item?.path();
+        for name in COMPILED_BINARIES {
+            let binary = workspace.join(name);
+            if binary.is_file() {
+                fs::remove_file(binary)?;
+            }
+        }
+    }
+
+    // AFL++ instances (one directory per instance, e.g., `default`)
+    let afl_out = output.join("aflpp").join("output");
+    if afl_out.is_dir() {
+        for item in fs::read_dir(&afl_out)? {
+            let instance = item?.path();
+            if !instance.is_dir() {
+                continue;
+            }
+            for name in AFLPP_SCRATCH {
+                let path = instance.join(name);
+                if path.exists() {
+                    remove_path(&path)?;
+                }
+            }
+            for name in AFLPP_ARCHIVED {
+                let path = instance.join(name);
+                if path.is_dir() {
+                    archive_dir(&path)?;
+                }
+            }
+        }
+    }
+
+    let after = disk_usage(&output)?;
+    info!(
+        "packet {} compacted: {} KiB -> {} KiB",
+        packet.id(),
+        before / 1024,
+        after / 1024
+    );
+    Ok(())
+}
+
+/// Enforce the disk quota by evicting artifacts of least-recently-used packets
+pub fn collect_garbage(registry: &Registry, quota: u64) -> Result<GcReport> {
+    // measure the current usage
+    let mut candidates = vec![];
+    let mut total = 0;
+    for record in registry.records()? {
+        let packet = Packet::from_id(record.hash.clone());
+        let path = registry.packet_path(&packet);
+        if !path.exists() {
+            continue;
+        }
+        let usage = disk_usage(&path)?;
+        total += usage;
+
+        // only evict artifacts of packets that are not in flight
+        let evictable = match Status::parse(&record.status)? {
+            Status::Received | Status::Running => false,
+            Status::Completed | Status::Error => record.evicted_at.is_none(),
+        };
+        if evictable && path.join("output").exists() {
+            candidates.push((record.last_access(), packet));
+        }
+    }
+    let total_before = total;
+
+    // evict in least-recently-used order until under quota
+    candidates.sort_by_key(|(ts, _)| *ts);
+    let mut evicted = vec![];
+    for (_, packet) in candidates {
+        if total <= quota {
+            break;
+        }
+        // the status is checked again by the registry, as it may have changed since the snapshot
+        let usage = match registry.evict(&packet) {
+            Ok(Some(usage)) => usage,
+            Ok(None) => {
+                info!("packet {} is in flight again, not evicted", packet.id());
+                continue;
+            }
+            Err(err) => {
+                warn!(
+                    "unable to evict artifacts of packet {}: {}",
+                    packet.id(),
+                    err
+                );
+                continue;
+            }
+        };
+        total = total.saturating_sub(usage);
+        info!("packet {} evicted: {} KiB freed", packet.id(), usage / 1024);
+        evicted.push(packet);
+    }
+
+    if total > quota {
+        warn!(
+            "disk usage {} MiB still exceeds quota {} MiB after eviction",
+            total / 1024 / 1024,
+            quota / 1024 / 1024
+        );
+    }
+    Ok(GcReport {
+        total_before,
+        total_after: total,
+        evicted,
+    })
+}
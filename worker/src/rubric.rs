@@ -0,0 +1,459 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::process::AnalysisResult;
+
+/// Quantities of an analysis result that criteria of a rubric are evaluated on
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// 1 if the program compiles, 0 otherwise
+    Compiled,
+    /// percentage of the test cases under `input/` that execute successfully
+    InputPassRate,
+    /// percentage of the test cases under `crash/` that actually crash the program
+    CrashPassRate,
+    /// number of test cases under `input/` printing unexpected output
+    OutputMismatches,
+    /// percentage of the hidden test cases that pass
+    HiddenPassRate,
+    /// number of issues reported by the sanitizers
+    SanitizerFindings,
+    /// percentage of blocks covered by the test cases under `input/`
+    GcovCoverage,
+    /// percentage of the compiling mutants of the program killed by the test cases
+    MutationScore,
+    /// number of unique crashes found by AFL++
+    AflppCrashes,
+    /// number of unique hangs found by AFL++
+    AflppHangs,
+    /// number of inputs on which the program diverges from the reference program
+    Divergences,
+}
+
+impl Metric {
+    /// All metrics, in the order they are reported
+    pub const ALL: &'static [Metric] = &[
+        Self::Compiled,
+        Self::InputPassRate,
+        Self::CrashPassRate,
+        Self::OutputMismatches,
+        Self::HiddenPassRate,
+        Self::SanitizerFindings,
+        Self::GcovCoverage,
+        Self::MutationScore,
+        Self::AflppCrashes,
+        Self::AflppHangs,
+        Self::Divergences,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Compiled => "compiled",
+            Self::InputPassRate => "input_pass_rate",
+            Self::CrashPassRate => "crash_pass_rate",
+            Self::OutputMismatches => "output_mismatches",
+            Self::HiddenPassRate => "hidden_pass_rate",
+            Self::SanitizerFindings => "sanitizer_findings",
+            Self::GcovCoverage => "gcov_coverage",
+            Self::MutationScore => "mutation_score",
+            Self::AflppCrashes => "aflpp_crashes",
+            Self::AflppHangs => "aflpp_hangs",
+            Self::Divergences => "divergences",
+        }
+    }
+
+    /// Value of the metric on an analysis result, `None` if the stage it derives from did not
+    /// run or did not complete
+    pub(crate) fn evaluate(&self, result: &AnalysisResult) -> Option<f64> {
+        let percent =
+            |part: usize, total: usize| (total != 0).then(|| part as f64 / total as f64 * 100.0);
+        let baseline = &result.result_baseline;
+        match self {
+            Self::Compiled => Some(if baseline.compiled { 1.0 } else { 0.0 }),
+            // timeouts weigh like the verdict does: as failures, or not at all
+            Self::InputPassRate => {
+                let timeouts = if baseline.hangs_as_failures {
+                    baseline.input_timeouts.len()
+                } else {
+                    0
+                };
+                percent(
+                    baseline.input_pass,
+                    baseline.input_pass + baseline.input_fail + timeouts,
+                )
+            }
+            Self::CrashPassRate => percent(
+                baseline.crash_pass,
+                baseline.crash_pass + baseline.crash_fail,
+            ),
+            Self::OutputMismatches => Some(
+                baseline
+                    .input_cases
+                    .iter()
+                    .filter(|c| c.is_mismatch())
+                    .count() as f64,
+            ),
+            Self::HiddenPassRate => {
+                let hidden = result.result_hidden.as_ref()?;
+                let total = hidden.input_cases.len() + hidden.crash_cases.len();
+                let (input, crash) = hidden.failures();
+                if hidden.compiled {
+                    percent(total - input - crash, total)
+                } else {
+                    Some(0.0)
+                }
+            }
+            Self::SanitizerFindings => {
+                let sanitizer = result.result_sanitizer.as_ref()?;
+                sanitizer
+                    .runs
+                    .iter()
+                    .all(|r| r.compiled)
+                    .then_some(sanitizer.findings.len() as f64)
+            }
+            Self::GcovCoverage => {
+                let gcov = &result.result_gcov;
+                if gcov.completed {
+                    percent(gcov.cov_blocks, gcov.num_blocks)
+                } else {
+                    None
+                }
+            }
+            Self::MutationScore => result.result_mutation.as_ref()?.score(),
+            Self::AflppCrashes => {
+                let aflpp = &result.result_aflpp;
+                aflpp.completed.then_some(aflpp.num_crashes as f64)
+            }
+            Self::AflppHangs => {
+                let aflpp = &result.result_aflpp;
+                aflpp.completed.then_some(aflpp.num_hangs as f64)
+            }
+            Self::Divergences => {
+                let differential = result.result_differential.as_ref()?;
+                (differential.compiled && differential.reference_compiled)
+                    .then_some(differential.num_divergences as f64)
+            }
+        }
+    }
+}
+
+/// A weighted criterion of a rubric
+///
+/// The full weight is awarded if the metric lies within `[min, max]` (either bound optional),
+/// or, if `scale` is given as `[zero, full]`, a share of it growing linearly from nothing at
+/// `zero` to the full weight at `full` (e.g., `[50, 100]` for a coverage percentage, or `[5, 0]`
+/// for a number of crashes).
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Criterion {
+    pub name: String,
+    pub weight: f64,
+    pub metric: Metric,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub scale: Option<(f64, f64)>,
+}
+
+impl Criterion {
+    /// Check the criterion
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("rubric criterion without a name");
+        }
+        if !self.weight.is_finite() || self.weight <= 0.0 {
+            bail!("rubric criterion {}: weight must be positive", self.name);
+        }
+        match self.scale {
+            None => {
+                if self.min.is_none() && self.max.is_none() {
+                    bail!("rubric criterion {}: needs min, max, or scale", self.name);
+                }
+                if let (Some(min), Some(max)) = (self.min, self.max) {
+                    if min > max {
+                        bail!("rubric criterion {}: min is above max", self.name);
+                    }
+                }
+            }
+            Some((zero, full)) => {
+                if self.min.is_some() || self.max.is_some() {
+                    bail!(
+                        "rubric criterion {}: scale cannot be combined with min or max",
+                        self.name
+                    );
+                }
+                if !zero.is_finite() || !full.is_finite() || zero == full {
+                    bail!("rubric criterion {}: scale must span a range", self.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Share of the weight awarded for a value of the metric, between 0 and 1
+    fn share(&self, value: f64) -> f64 {
+        match self.scale {
+            Some((zero, full)) => ((value - zero) / (full - zero)).clamp(0.0, 1.0),
+            None => {
+                let above = self.min.is_none_or(|min| value >= min);
+                let below = self.max.is_none_or(|max| value <= max);
+                if above && below {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Condition on the metric for the full weight, e.g., `gcov_coverage >= 90`
+    fn to_human_readable(&self) -> String {
+        let metric = self.metric.as_str();
+        match (self.scale, self.min, self.max) {
+            (Some((zero, full)), _, _) => format!("{} from {} to {}", metric, zero, full),
+            (None, Some(min), Some(max)) => format!("{} <= {} <= {}", min, metric, max),
+            (None, Some(min), None) => format!("{} >= {}", metric, min),
+            (None, None, Some(max)) => format!("{} <= {}", metric, max),
+            (None, None, None) => metric.to_string(),
+        }
+    }
+}
+
+/// Rubric of an assignment, as a list of weighted criteria
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Rubric(pub Vec<Criterion>);
+
+impl Rubric {
+    /// Check the criteria
+    pub(crate) fn validate(&self) -> Result<()> {
+        for (i, criterion) in self.0.iter().enumerate() {
+            criterion.validate()?;
+            if self.0[..i].iter().any(|c| c.name == criterion.name) {
+                bail!("duplicated rubric criterion: {}", criterion.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Score an analysis result, `None` if the rubric has no criteria
+    pub fn grade(&self, result: &AnalysisResult) -> Option<RubricScore> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let criteria: Vec<_> = self
+            .0
+            .iter()
+            .map(|criterion| {
+                let value = criterion.metric.evaluate(result);
+                CriterionScore {
+                    name: criterion.name.clone(),
+                    condition: criterion.to_human_readable(),
+                    value,
+                    score: value.map_or(0.0, |v| criterion.share(v) * criterion.weight),
+                    weight: criterion.weight,
+                }
+            })
+            .collect();
+        Some(RubricScore {
+            score: criteria.iter().map(|c| c.score).sum(),
+            total: criteria.iter().map(|c| c.weight).sum(),
+            criteria,
+        })
+    }
+}
+
+/// Score awarded for one criterion
+#[derive(Serialize, Deserialize)]
+pub struct CriterionScore {
+    pub name: String,
+    pub condition: String,
+    /// value of the metric, `None` if it is not available
+    pub value: Option<f64>,
+    pub score: f64,
+    pub weight: f64,
+}
+
+/// Score of an analysis result under a rubric
+#[derive(Serialize, Deserialize)]
+pub struct RubricScore {
+    pub score: f64,
+    pub total: f64,
+    pub criteria: Vec<CriterionScore>,
+}
+
+impl RubricScore {
+    pub fn to_human_readable(&self) -> String {
+        let mut lines = vec![format!("score: {:.2} / {:.2}", self.score, self.total)];
+        for criterion in &self.criteria {
+            let value = criterion
+                .value
+                .map_or("not available".to_string(), |v| format!("{:.2}", v));
+            lines.push(format!(
+                "- {}: {:.2} / {:.2} ({}, measured {})",
+                criterion.name, criterion.score, criterion.weight, criterion.condition, value
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn criterion(metric: Metric, bounds: (Option<f64>, Option<f64>)) -> Criterion {
+        Criterion {
+            name: metric.as_str().to_string(),
+            weight: 10.0,
+            metric,
+            min: bounds.0,
+            max: bounds.1,
+            scale: None,
+        }
+    }
+
+    fn scaled(metric: Metric, zero: f64, full: f64) -> Criterion {
+        Criterion {
+            scale: Some((zero, full)),
+            ..criterion(metric, (None, None))
+        }
+    }
+
+    fn result(input: (usize, usize, usize), hangs_as_failures: bool) -> AnalysisResult {
+        serde_json::from_value(json!({
+            "result_baseline": {
+                "compiled": true,
+                "input_pass": input.0,
+                "input_fail": input.1,
+                "crash_pass": 1,
+                "crash_fail": 0,
+                "input_timeouts": vec!["t"; input.2],
+                "hangs_as_failures": hangs_as_failures,
+            },
+            "result_gcov": { "completed": true, "num_blocks": 40, "cov_blocks": 30 },
+            "result_aflpp": { "completed": true, "num_crashes": 1 },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn validate() {
+        let min = criterion(Metric::Compiled, (Some(1.0), None));
+        assert!(min.validate().is_ok());
+        assert!(criterion(Metric::Compiled, (None, Some(0.0)))
+            .validate()
+            .is_ok());
+        assert!(criterion(Metric::Compiled, (Some(1.0), Some(1.0)))
+            .validate()
+            .is_ok());
+        assert!(scaled(Metric::AflppCrashes, 5.0, 0.0).validate().is_ok());
+
+        assert!(criterion(Metric::Compiled, (None, None))
+            .validate()
+            .is_err());
+        assert!(criterion(Metric::Compiled, (Some(2.0), Some(1.0)))
+            .validate()
+            .is_err());
+        for weight in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Criterion {
+                weight,
+                ..min.clone()
+            }
+            .validate()
+            .is_err());
+        }
+        assert!(Criterion {
+            name: String::new(),
+            ..min.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(scaled(Metric::GcovCoverage, 50.0, 50.0).validate().is_err());
+        assert!(scaled(Metric::GcovCoverage, 0.0, f64::INFINITY)
+            .validate()
+            .is_err());
+        assert!(Criterion {
+            min: Some(0.0),
+            ..scaled(Metric::GcovCoverage, 50.0, 100.0)
+        }
+        .validate()
+        .is_err());
+
+        assert!(
+            Rubric(vec![min.clone(), scaled(Metric::GcovCoverage, 50.0, 100.0)])
+                .validate()
+                .is_ok()
+        );
+        assert!(Rubric(vec![min.clone(), min]).validate().is_err());
+    }
+
+    #[test]
+    fn share_bounds() {
+        let min = criterion(Metric::GcovCoverage, (Some(90.0), None));
+        assert_eq!(min.share(89.9), 0.0);
+        assert_eq!(min.share(90.0), 1.0);
+        assert_eq!(min.share(100.0), 1.0);
+
+        let max = criterion(Metric::AflppCrashes, (None, Some(0.0)));
+        assert_eq!(max.share(0.0), 1.0);
+        assert_eq!(max.share(1.0), 0.0);
+
+        let both = criterion(Metric::OutputMismatches, (Some(1.0), Some(3.0)));
+        assert_eq!(both.share(0.0), 0.0);
+        assert_eq!(both.share(1.0), 1.0);
+        assert_eq!(both.share(3.0), 1.0);
+        assert_eq!(both.share(4.0), 0.0);
+    }
+
+    #[test]
+    fn share_scale() {
+        let scale = scaled(Metric::GcovCoverage, 50.0, 100.0);
+        assert_eq!(scale.share(0.0), 0.0);
+        assert_eq!(scale.share(50.0), 0.0);
+        assert_eq!(scale.share(75.0), 0.5);
+        assert_eq!(scale.share(100.0), 1.0);
+        assert_eq!(scale.share(150.0), 1.0);
+
+        // fewer is better
+        let inverted = scaled(Metric::AflppCrashes, 5.0, 0.0);
+        assert_eq!(inverted.share(0.0), 1.0);
+        assert_eq!(inverted.share(1.0), 0.8);
+        assert_eq!(inverted.share(5.0), 0.0);
+        assert_eq!(inverted.share(10.0), 0.0);
+    }
+
+    #[test]
+    fn grade() {
+        assert!(Rubric::default().grade(&result((3, 1, 0), true)).is_none());
+
+        let rubric = Rubric(vec![
+            criterion(Metric::Compiled, (Some(1.0), None)),
+            scaled(Metric::InputPassRate, 50.0, 100.0),
+            scaled(Metric::GcovCoverage, 50.0, 100.0),
+            scaled(Metric::AflppCrashes, 5.0, 0.0),
+            // the mutation stage did not run
+            criterion(Metric::MutationScore, (Some(0.0), None)),
+        ]);
+        let score = rubric.grade(&result((3, 1, 0), true)).unwrap();
+        let values: Vec<_> = score.criteria.iter().map(|c| c.value).collect();
+        assert_eq!(values, [Some(1.0), Some(75.0), Some(75.0), Some(1.0), None]);
+        let scores: Vec<_> = score.criteria.iter().map(|c| c.score).collect();
+        assert_eq!(scores, [10.0, 5.0, 5.0, 8.0, 0.0]);
+        assert_eq!(score.score, 28.0);
+        assert_eq!(score.total, 50.0);
+    }
+
+    #[test]
+    fn input_pass_rate_timeouts() {
+        let metric = Metric::InputPassRate;
+        assert_eq!(metric.evaluate(&result((3, 0, 1), true)), Some(75.0));
+        assert_eq!(metric.evaluate(&result((3, 0, 1), false)), Some(100.0));
+        assert_eq!(metric.evaluate(&result((0, 0, 0), true)), None);
+    }
+}
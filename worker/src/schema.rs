@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::packet::{Packet, Registry, FILE_PREVIOUS_RESULT, MARKER_RESULT, SUFFIX_TEMP};
+use crate::process::AnalysisResult;
+use crate::rubric::RubricScore;
+use crate::tool_aflpp::{BudgetReport, ConfigAFLpp};
+use crate::tool_aflpp_stats::MetricsAFLpp;
+use crate::tool_differential::ResultDifferential;
+use crate::tool_gcov::ResultHidden;
+use crate::tool_mutation::ResultMutation;
+use crate::tool_sanitizer::ResultSanitizer;
+
+/// Version of the schema of stored analysis results, to be bumped together with a migration
+/// whenever a change to `AnalysisResult` (or anything it holds) is not absorbed by
+/// `#[serde(default)]`, e.g., a renamed field or a new field without a default
+pub const RESULT_SCHEMA_VERSION: u32 = 1;
+
+/// Name of the field holding the schema version in a stored result
+const FIELD_VERSION: &str = "schema_version";
+
+/// A migration of a stored result from one schema version to the next
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// Migrations of stored results, the i-th one upgrading from version i to i + 1
+const MIGRATIONS: &[Migration] = &[
+    // 0 -> 1: results stored before versioning, the fields added since then all have defaults
+    |_| Ok(()),
+];
+
+const _: () = assert!(MIGRATIONS.len() == RESULT_SCHEMA_VERSION as usize);
+
+/// Read a stored result and migrate it to the current schema, returns the result as JSON
+/// together with the schema version it is stored in
+fn migrate(path: &Path) -> Result<(Map<String, Value>, u32)> {
+    let mut doc = match serde_json::from_slice(&fs::read(path)?)? {
+        Value::Object(doc) => doc,
+        _ => bail!("analysis result is not a JSON object"),
+    };
+    let version = match doc.get(FIELD_VERSION) {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("invalid result schema version: {}", v))?,
+    };
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut doc).map_err(|e| {
+            anyhow!(
+                "unable to migrate result to schema version {}: {}",
+                i + 1,
+                e
+            )
+        })?;
+    }
+    if version < RESULT_SCHEMA_VERSION {
+        doc.insert(FIELD_VERSION.to_string(), RESULT_SCHEMA_VERSION.into());
+    }
+    Ok((doc, version))
+}
+
+/// Check whether a section of a result parses as the given type
+fn parses<T: DeserializeOwned>(value: &Value) -> bool {
+    value.is_null() || serde_json::from_value::<T>(value.clone()).is_ok()
+}
+
+/// A check of whether a section of a result can be parsed
+type SectionCheck = fn(&Value) -> bool;
+
+/// Optional sections of a result, by path, which are left out if they cannot be parsed
+const OPTIONAL_SECTIONS: &[(&[&str], SectionCheck)] = &[
+    (&["result_hidden"], parses::<ResultHidden>),
+    (&["result_sanitizer"], parses::<ResultSanitizer>),
+    (&["result_mutation"], parses::<ResultMutation>),
+    (&["result_differential"], parses::<ResultDifferential>),
+    (&["rubric"], parses::<RubricScore>),
+    (&["result_aflpp", "metrics"], parses::<MetricsAFLpp>),
+    (&["result_aflpp", "config"], parses::<ConfigAFLpp>),
+    (&["result_aflpp", "budget"], parses::<BudgetReport>),
+];
+
+/// Drop the optional sections of a result that cannot be parsed, returns their paths
+fn drop_broken_sections(doc: &mut Map<String, Value>) -> Vec<String> {
+    let mut dropped = vec![];
+    for (path, check) in OPTIONAL_SECTIONS {
+        let (key, parents) = path.split_last().expect("section path");
+        let mut parent = Some(&mut *doc);
+        for seg in parents {
+            parent = parent
+                .and_then(|p| p.get_mut(*seg))
+                .and_then(|v| v.as_object_mut());
+        }
+        if let Some(parent) = parent {
+            if parent.get(*key).is_some_and(|v| !check(v)) {
+                parent.remove(*key);
+                dropped.push(path.join("."));
+            }
+        }
+    }
+    dropped
+}
+
+/// Load a stored result of any schema version, migrating it to the current one; the optional
+/// sections that still cannot be parsed (e.g., from a newer version) are left out with a warning
+/// rather than failing the whole result
+pub fn load(path: &Path) -> Result<AnalysisResult> {
+    let (mut doc, version) = migrate(path)?;
+    if version > RESULT_SCHEMA_VERSION {
+        warn!(
+            "{:?} is in result schema version {}, newer than {}",
+            path, version, RESULT_SCHEMA_VERSION
+        );
+    }
+    if let Ok(result) = serde_json::from_value(Value::Object(doc.clone())) {
+        return Ok(result);
+    }
+    let dropped = drop_broken_sections(&mut doc);
+    let result = serde_json::from_value(Value::Object(doc))?;
+    warn!("{:?} loaded without: {}", path, dropped.join(", "));
+    Ok(result)
+}
+
+/// Upgrade a stored result to the current schema version in place, returns the version it was
+/// stored in if it is upgraded (a result is never upgraded at the expense of its content)
+fn upgrade(path: &Path) -> Result<Option<u32>> {
+    let (doc, version) = migrate(path)?;
+    if version == RESULT_SCHEMA_VERSION {
+        return Ok(None);
+    }
+    if version > RESULT_SCHEMA_VERSION {
+        bail!(
+            "result schema version {} is newer than {}",
+            version,
+            RESULT_SCHEMA_VERSION
+        );
+    }
+
+    // round-trip through the current schema so that the stored result is complete
+    let result: AnalysisResult = serde_json::from_value(Value::Object(doc))?;
+    let path_tmp = path.with_extension(SUFFIX_TEMP);
+    fs::write(&path_tmp, serde_json::to_string_pretty(&result)?)?;
+    fs::rename(&path_tmp, path)?;
+    Ok(Some(version))
+}
+
+/// A stored result that is upgraded, or fails to be
+pub struct Upgrade {
+    pub path: PathBuf,
+    /// schema version the result was stored in, or why it cannot be upgraded
+    pub outcome: Result<u32, String>,
+}
+
+/// Upgrade the stored results (including the ones kept from before a re-grade) of all packets in
+/// the registry, returns those that are not in the current schema version already
+pub fn upgrade_all(registry: &Registry) -> Result<Vec<Upgrade>> {
+    let mut upgrades = vec![];
+    for record in registry.records()? {
+        let base = registry.packet_path(&Packet::from_id(record.hash));
+        for name in [MARKER_RESULT, FILE_PREVIOUS_RESULT] {
+            let path = base.join(name);
+            if !path.exists() {
+                continue;
+            }
+            let outcome = match upgrade(&path) {
+                Ok(None) => continue,
+                Ok(Some(version)) => Ok(version),
+                Err(err) => Err(err.to_string()),
+            };
+            upgrades.push(Upgrade { path, outcome });
+        }
+    }
+    Ok(upgrades)
+}
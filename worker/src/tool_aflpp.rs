@@ -1,13 +1,21 @@
 use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{bail, Result};
+use log::{info, warn};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
 
-use crate::packet::{Packet, Registry};
+use crate::assignment::Assignment;
+use crate::packet::{fmt_duration, Packet, Registry};
+use crate::tool_aflpp_stats::{LiveStats, MetricsAFLpp};
+use crate::util_archive::NamedFiles;
+use crate::util_clex::{tokenize, unescape, TokenKind};
 use crate::util_docker::{Dock, ExitStatus};
 
 /// Tag of the Docker image
@@ -17,7 +25,47 @@ const DOCKER_TAG: &str = "afl";
 const DOCKER_MNT: &str = "/test";
 
 /// Timeout for fuzzing
-const TIMEOUT_FUZZ: Duration = Duration::from_secs(60 * 15);
+pub(crate) const TIMEOUT_FUZZ: Duration = Duration::from_secs(60 * 15);
+
+/// Wall-clock cap for campaigns with a budget in executions or core time
+const TIMEOUT_FUZZ_CAP: Duration = Duration::from_secs(60 * 60);
+
+/// Fraction of the budget a campaign must consume to not be flagged as short
+const SHORT_RUN_TOLERANCE: f64 = 0.9;
+
+/// Interval between two snapshots of the fuzzing statistics
+const LIVE_STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Workspace directory where the statistics of each AFL++ instance are mirrored
+const LIVE_STATS_DIR: &str = "live";
+
+/// Name of the workspace of the fuzzing stage in the packet output directory
+pub(crate) const WORKSPACE: &str = "aflpp";
+
+/// File in the workspace recording the progress of a campaign, removed once it ends
+const CHECKPOINT: &str = "checkpoint.json";
+
+/// Directory collecting the unique hanging inputs of all instances, kept in the packet rather
+/// than in the workspace so that the inputs remain downloadable after eviction (packets analyzed
+/// before have it in the workspace)
+pub(crate) const HANGS_DIR: &str = "hangs";
+
+/// Workspace directory holding the dictionaries for fuzzing
+const DICT_DIR: &str = "dict";
+
+/// Name of the dictionary extracted from the program
+const AUTO_DICT: &str = "auto.dict";
+
+/// Maximum length of a dictionary entry accepted by AFL++
+const MAX_DICT_ENTRY: usize = 128;
+
+/// Maximum number of AFL++ instances fuzzing a packet in parallel
+const MAX_FUZZ_INSTANCES: usize = 8;
+
+/// Power schedules supported by `afl-fuzz -p`
+const POWER_SCHEDULES: &[&str] = &[
+    "explore", "fast", "exploit", "seek", "rare", "mmopt", "coe", "lin", "quad",
+];
 
 /// Path to the build directory
 static DOCKER_PATH: Lazy<PathBuf> = Lazy::new(|| {
@@ -33,69 +81,686 @@ pub fn provision(dock: &Dock, force: bool) -> Result<()> {
     Ok(())
 }
 
+/// Instrumentation modes of `afl-cc`
+#[derive(Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Instrumentation {
+    /// whatever `afl-cc` picks by default
+    #[default]
+    Default,
+    Pcguard,
+    Classic,
+    Lto,
+    Gcc,
+}
+
+impl Instrumentation {
+    /// Environment variables selecting this mode in `afl-cc`
+    fn env(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Default => &[],
+            Self::Pcguard => &[("AFL_LLVM_INSTRUMENT", "PCGUARD")],
+            Self::Classic => &[("AFL_LLVM_INSTRUMENT", "CLASSIC")],
+            Self::Lto => &[("AFL_CC_COMPILER", "LTO")],
+            Self::Gcc => &[("AFL_CC_COMPILER", "GCC_PLUGIN")],
+        }
+    }
+}
+
+/// Budget of a fuzzing campaign
+#[derive(Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Budget {
+    /// wall-clock time, i.e., `TIMEOUT_FUZZ`
+    #[default]
+    WallClock,
+    /// total executions over all instances (`-E`, split among instances)
+    Executions(u64),
+    /// total core time in seconds over all instances (`-V`, split among instances); as each
+    /// instance runs on a dedicated core, its running time is the core time it consumes
+    CoreTime(u64),
+}
+
+impl Budget {
+    /// Wall-clock time limit of the campaign
+    pub(crate) fn wall_clock_limit(&self) -> Duration {
+        match self {
+            Self::WallClock => TIMEOUT_FUZZ,
+            Self::Executions(_) | Self::CoreTime(_) => TIMEOUT_FUZZ_CAP,
+        }
+    }
+
+    /// Arguments for one of the instances sharing the budget, minus the core time already
+    /// spent before a resumption (AFL++ restores the execution count on resume, but measures
+    /// `-V` from the start of the current run)
+    fn instance_args(&self, instances: usize, spent: Option<&Checkpoint>) -> Vec<String> {
+        let instances = instances.max(1) as u64;
+        match self {
+            Self::WallClock => vec![],
+            Self::Executions(n) => vec!["-E".to_string(), n.div_ceil(instances).to_string()],
+            Self::CoreTime(secs) => {
+                let left = secs.saturating_sub(spent.map_or(0, |c| c.core_time_secs));
+                vec![
+                    "-V".to_string(),
+                    left.div_ceil(instances).max(1).to_string(),
+                ]
+            }
+        }
+    }
+
+    fn to_human_readable(self) -> String {
+        match self {
+            Self::WallClock => format!("{} wall-clock", fmt_duration(TIMEOUT_FUZZ)),
+            Self::Executions(n) => format!("{} executions", n),
+            Self::CoreTime(secs) => {
+                format!("{} core time", fmt_duration(Duration::from_secs(secs)))
+            }
+        }
+    }
+}
+
+/// Budget of a campaign and how much of it was consumed
+#[derive(Serialize, Deserialize)]
+pub struct BudgetReport {
+    pub budget: Budget,
+    pub wall_clock_secs: u64,
+    pub core_time_secs: u64,
+    pub executions: u64,
+    /// whether the campaign consumed noticeably less than its budget
+    pub short: bool,
+}
+
+impl BudgetReport {
+    fn new(budget: Budget, wall_clock: Duration, metrics: Option<&MetricsAFLpp>) -> Self {
+        let wall_clock_secs = wall_clock.as_secs();
+        let (core_time_secs, executions) = metrics.map_or((0, 0), |m| (m.core_time, m.execs_done));
+        let (consumed, target) = match budget {
+            Budget::WallClock => (wall_clock_secs, TIMEOUT_FUZZ.as_secs()),
+            Budget::Executions(n) => (executions, n),
+            Budget::CoreTime(secs) => (core_time_secs, secs),
+        };
+        Self {
+            budget,
+            wall_clock_secs,
+            core_time_secs,
+            executions,
+            short: (consumed as f64) < (target as f64) * SHORT_RUN_TOLERANCE,
+        }
+    }
+
+    fn to_human_readable(&self) -> String {
+        let consumed = format!(
+            "{} executions, {} core time, {} wall-clock",
+            self.executions,
+            fmt_duration(Duration::from_secs(self.core_time_secs)),
+            fmt_duration(Duration::from_secs(self.wall_clock_secs))
+        );
+        let mut line = format!(
+            "- budget: {} (consumed {})",
+            self.budget.to_human_readable(),
+            consumed
+        );
+        if self.short {
+            line.push_str("\n[warning] the campaign ran short of its budget");
+        }
+        line
+    }
+}
+
+/// Progress of a campaign, kept up to date while fuzzing so that a campaign interrupted by a
+/// restart can be resumed with the remainder of its budget
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    pub instances: usize,
+    pub wall_clock_secs: u64,
+    pub core_time_secs: u64,
+    pub executions: u64,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint of a campaign left in the fuzzing workspace of a packet, if any
+    pub(crate) fn load(registry: &Registry, packet: &Packet) -> Option<Self> {
+        let path = workspace(&registry.packet_path(packet)).join(CHECKPOINT);
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save(&self, host_workspace: &Path) -> Result<()> {
+        let path = host_workspace.join(CHECKPOINT);
+        let path_tmp = path.with_extension("tmp");
+        fs::write(&path_tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&path_tmp, &path)?;
+        Ok(())
+    }
+
+    /// Wall-clock time spent on the campaign so far
+    pub(crate) fn wall_clock(&self) -> Duration {
+        Duration::from_secs(self.wall_clock_secs)
+    }
+}
+
+/// Fuzzing workspace in a packet directory
+fn workspace(packet_path: &Path) -> PathBuf {
+    packet_path.join("output").join(WORKSPACE)
+}
+
+/// Whether a packet directory holds an interrupted campaign that can be resumed
+pub(crate) fn is_resumable(packet_path: &Path) -> bool {
+    workspace(packet_path).join(CHECKPOINT).is_file()
+}
+
+/// Options of an AFL++ campaign
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigAFLpp {
+    pub instrumentation: Instrumentation,
+    /// build an extra CMPLOG binary and fuzz with `-c`, at this `-l` level (e.g., `2` or `2AT`)
+    pub cmplog: Option<String>,
+    /// split comparisons and string compares (laf-intel) in the main binary
+    pub laf_intel: bool,
+    /// build the main binary with AddressSanitizer to catch memory errors that do not crash
+    pub asan: bool,
+    /// dictionary files provided with the assignment
+    pub dictionaries: Vec<String>,
+    /// extract a dictionary from the string literals in the program
+    pub auto_dictionary: bool,
+    pub power_schedule: Option<String>,
+    pub seed: Option<u64>,
+    /// extra environment variables for `afl-fuzz`, only `AFL_*` ones are accepted
+    pub env: BTreeMap<String, String>,
+    pub budget: Budget,
+}
+
+impl ConfigAFLpp {
+    /// Check the options before they ever reach a command line
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(level) = self.cmplog.as_deref() {
+            let mut chars = level.chars();
+            if !matches!(chars.next(), Some('1'..='3')) || !chars.all(|c| "ATXR".contains(c)) {
+                bail!("invalid CMPLOG level: {}", level);
+            }
+        }
+        if let Some(schedule) = self.power_schedule.as_deref() {
+            if !POWER_SCHEDULES.contains(&schedule) {
+                bail!("invalid power schedule: {}", schedule);
+            }
+        }
+        for (key, val) in &self.env {
+            if !key.starts_with("AFL_")
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            {
+                bail!("invalid environment variable for AFL++: {}", key);
+            }
+            if val.contains('\0') {
+                bail!("invalid value for environment variable {}", key);
+            }
+        }
+        match self.budget {
+            Budget::Executions(0) | Budget::CoreTime(0) => bail!("budget must not be zero"),
+            Budget::CoreTime(secs)
+                if secs > TIMEOUT_FUZZ_CAP.as_secs() * MAX_FUZZ_INSTANCES as u64 =>
+            {
+                bail!("core time budget is too large")
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Check whether this is the plain setup, i.e., nothing configured
+    fn is_plain(&self) -> bool {
+        self.instrumentation == Instrumentation::Default
+            && self.cmplog.is_none()
+            && !self.laf_intel
+            && !self.asan
+            && self.dictionaries.is_empty()
+            && !self.auto_dictionary
+            && self.power_schedule.is_none()
+            && self.seed.is_none()
+            && self.env.is_empty()
+            && self.budget == Budget::WallClock
+    }
+
+    fn to_human_readable(&self) -> String {
+        let mut items = vec![];
+        if self.instrumentation != Instrumentation::Default {
+            let mode = serde_json::to_value(self.instrumentation)
+                .ok()
+                .and_then(|v| v.as_str().map(|s| s.to_string()))
+                .unwrap_or_default();
+            items.push(format!("instrumentation={}", mode));
+        }
+        if let Some(level) = self.cmplog.as_ref() {
+            items.push(format!("cmplog={}", level));
+        }
+        if self.laf_intel {
+            items.push("laf-intel".to_string());
+        }
+        if self.asan {
+            items.push("asan".to_string());
+        }
+        for dict in &self.dictionaries {
+            items.push(format!("dict={}", dict));
+        }
+        if self.auto_dictionary {
+            items.push("dict=<auto>".to_string());
+        }
+        if let Some(schedule) = self.power_schedule.as_ref() {
+            items.push(format!("schedule={}", schedule));
+        }
+        if let Some(seed) = self.seed {
+            items.push(format!("seed={}", seed));
+        }
+        for (key, val) in &self.env {
+            items.push(format!("{}={}", key, val));
+        }
+        if self.budget != Budget::WallClock {
+            items.push(format!("budget={}", self.budget.to_human_readable()));
+        }
+        items.join(", ")
+    }
+}
+
 /// Result for AFL++ fuzzing
 #[derive(Serialize, Deserialize)]
 pub struct ResultAFLpp {
     pub completed: bool,
     pub num_crashes: u64,
+    #[serde(default)]
+    pub num_hangs: u64,
+    /// whether hangs are counted as failures by the assignment
+    #[serde(default)]
+    pub hangs_as_failures: bool,
+    #[serde(default)]
+    pub metrics: Option<MetricsAFLpp>,
+    #[serde(default)]
+    pub config: Option<ConfigAFLpp>,
+    #[serde(default)]
+    pub instances: Option<usize>,
+    #[serde(default)]
+    pub cores: Option<Vec<usize>>,
+    #[serde(default)]
+    pub budget: Option<BudgetReport>,
+    /// wall-clock seconds spent before the campaign was interrupted and resumed
+    #[serde(default)]
+    pub resumed: Option<u64>,
 }
 
 impl ResultAFLpp {
     pub fn to_human_readable(&self) -> String {
-        if !self.completed {
-            return "[failure] unable to complete AFL++ fuzzing".to_string();
+        let verdict = if !self.completed {
+            "[failure] unable to complete AFL++ fuzzing".to_string()
+        } else if self.num_crashes != 0 {
+            format!("[failure] AFL++ found {} unique crashes", self.num_crashes)
+        } else if self.num_hangs != 0 && self.hangs_as_failures {
+            format!("[failure] AFL++ found {} unique hangs", self.num_hangs)
+        } else {
+            "[success] AFL++ found no crashes".to_string()
+        };
+        let mut lines = vec![verdict];
+        if self.num_hangs != 0 {
+            lines.push(format!(
+                "- hangs: {} unique input(s) running into the timeout",
+                self.num_hangs
+            ));
         }
-        if self.num_crashes != 0 {
-            return format!("[failure] AFL++ found {} crashes", self.num_crashes);
+        if let Some(count) = self.instances.filter(|n| *n > 1) {
+            lines.push(format!("- instances: {} in parallel", count));
         }
-        "[success] AFL++ found no crashes".to_string()
+        if let Some(cores) = self.cores.as_ref() {
+            let cores: Vec<_> = cores.iter().map(|c| c.to_string()).collect();
+            lines.push(format!("- cores: {}", cores.join(",")));
+        }
+        if let Some(config) = self.config.as_ref().filter(|c| !c.is_plain()) {
+            lines.push(format!("- options: {}", config.to_human_readable()));
+        }
+        if let Some(budget) = self.budget.as_ref() {
+            lines.push(budget.to_human_readable());
+        }
+        if let Some(secs) = self.resumed {
+            lines.push(format!(
+                "- resumed after an interruption at {}",
+                fmt_duration(Duration::from_secs(secs))
+            ));
+        }
+        if let Some(metrics) = self.metrics.as_ref() {
+            lines.push(metrics.to_human_readable());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Extract a dictionary from the string literals in a C program
+fn extract_dictionary(program: &Path) -> Result<Vec<Vec<u8>>> {
+    let src = fs::read_to_string(program)?;
+    let mut entries = vec![];
+    for token in tokenize(&src) {
+        if !matches!(token.kind, TokenKind::Str | TokenKind::Char) {
+            continue;
+        }
+        let value = unescape(token.text);
+        if value.is_empty() || value.len() > MAX_DICT_ENTRY || entries.contains(&value) {
+            continue;
+        }
+        entries.push(value);
     }
+    Ok(entries)
 }
 
-pub fn run_aflpp(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<ResultAFLpp> {
-    let docked = registry.mk_dockerized_packet(packet, "aflpp", DOCKER_MNT)?;
+/// Render dictionary entries in the AFL++ dictionary format
+fn format_dictionary(entries: &[Vec<u8>]) -> String {
+    let mut text = String::new();
+    for (i, entry) in entries.iter().enumerate() {
+        text.push_str(&format!("auto_{}=\"", i));
+        for &b in entry {
+            if (b.is_ascii_graphic() && b != b'"' && b != b'\\') || b == b' ' {
+                text.push(b as char);
+            } else {
+                text.push_str(&format!("\\x{:02x}", b));
+            }
+        }
+        text.push_str("\"\n");
+    }
+    text
+}
+
+/// Quote a string for the shell
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Prefix a command with `env` to set the given variables
+fn with_env<'a>(
+    vars: impl IntoIterator<Item = (&'a str, &'a str)>,
+    cmd: Vec<String>,
+) -> Vec<String> {
+    let mut full = vec!["env".to_string()];
+    full.extend(vars.into_iter().map(|(k, v)| format!("{}={}", k, v)));
+    full.extend(cmd);
+    full
+}
+
+pub fn run_aflpp(
+    dock: &Dock,
+    registry: &Registry,
+    packet: &Packet,
+    assignment: &Assignment,
+    cores: &[usize],
+) -> Result<ResultAFLpp> {
+    let config = &assignment.aflpp;
+
+    // pick up an interrupted campaign, unless it ran with a different number of instances
+    let instances = cores.len().max(1);
+    let checkpoint = Checkpoint::load(registry, packet).filter(|c| c.instances == instances);
+    let docked = match checkpoint.as_ref() {
+        None => registry.mk_dockerized_packet(packet, WORKSPACE, DOCKER_MNT)?,
+        Some(spent) => {
+            info!(
+                "resuming AFL++ campaign on packet {} after {}",
+                packet.id(),
+                fmt_duration(spent.wall_clock())
+            );
+            registry.reuse_dockerized_packet(packet, WORKSPACE, DOCKER_MNT)?
+        }
+    };
+    let resumed = checkpoint.as_ref().map(|c| c.wall_clock_secs);
+    let failure = || ResultAFLpp {
+        completed: false,
+        num_crashes: 0,
+        num_hangs: 0,
+        hangs_as_failures: assignment.hangs_as_failures,
+        metrics: None,
+        config: Some(config.clone()),
+        instances: None,
+        cores: None,
+        budget: None,
+        resumed,
+    };
 
     // compile the program
     let (_, dock_path_compiled) = docked.wks_path("main");
+    let mut compile_env = config.instrumentation.env().to_vec();
+    if config.laf_intel {
+        compile_env.push(("AFL_LLVM_LAF_ALL", "1"));
+    }
+    if config.asan {
+        compile_env.push(("AFL_USE_ASAN", "1"));
+    }
     let result = docker_run(
         dock,
         &docked.host_base,
-        vec![
-            "afl-cc".to_string(),
-            docked.path_program.clone(),
-            "-o".to_string(),
-            dock_path_compiled.clone(),
-        ],
+        with_env(
+            compile_env,
+            vec![
+                "afl-cc".to_string(),
+                docked.path_program.clone(),
+                "-o".to_string(),
+                dock_path_compiled.clone(),
+            ],
+        ),
         None,
     )?;
     if !matches!(result, ExitStatus::Success) {
-        return Ok(ResultAFLpp {
-            completed: false,
-            num_crashes: 0,
-        });
+        return Ok(failure());
+    }
+
+    // compile the CMPLOG variant (without laf-intel, which does not mix with CMPLOG, and
+    // without ASan, which only slows the CMPLOG binary down)
+    let mut cmplog_args = vec![];
+    if let Some(level) = config.cmplog.as_ref() {
+        let (_, dock_path_cmplog) = docked.wks_path("main.cmplog");
+        let mut cmplog_env = config.instrumentation.env().to_vec();
+        cmplog_env.push(("AFL_LLVM_CMPLOG", "1"));
+        let result = docker_run(
+            dock,
+            &docked.host_base,
+            with_env(
+                cmplog_env,
+                vec![
+                    "afl-cc".to_string(),
+                    docked.path_program.clone(),
+                    "-o".to_string(),
+                    dock_path_cmplog.clone(),
+                ],
+            ),
+            None,
+        )?;
+        if !matches!(result, ExitStatus::Success) {
+            return Ok(failure());
+        }
+        cmplog_args.extend([
+            "-c".to_string(),
+            dock_path_cmplog,
+            "-l".to_string(),
+            level.clone(),
+        ]);
+    }
+
+    // prepare the dictionaries
+    let (host_path_dict, dock_path_dict) = docked.wks_path(DICT_DIR);
+    let mut dicts = vec![];
+    for name in &config.dictionaries {
+        dicts.push((name.clone(), fs::read(assignment.resolve(name)?)?));
+    }
+    if config.auto_dictionary {
+        let entries = extract_dictionary(&docked.host_base.join("main.c"))?;
+        if !entries.is_empty() {
+            dicts.push((
+                AUTO_DICT.to_string(),
+                format_dictionary(&entries).into_bytes(),
+            ));
+        }
+    }
+    let mut fuzz_args = vec![];
+    if !dicts.is_empty() {
+        fs::create_dir_all(&host_path_dict)?;
+        for (name, content) in dicts {
+            fs::write(host_path_dict.join(&name), content)?;
+            fuzz_args.extend(["-x".to_string(), format!("{}/{}", dock_path_dict, name)]);
+        }
+    }
+
+    // campaign options
+    if let Some(schedule) = config.power_schedule.as_ref() {
+        fuzz_args.extend(["-p".to_string(), schedule.clone()]);
     }
+    let fuzz_env: Vec<_> = config
+        .env
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, sh_quote(v)))
+        .collect();
 
-    // fuzz the program
+    // one main instance and secondaries syncing with it, or a plain single instance;
+    // only the main instance runs CMPLOG and secondaries get their own seeds;
+    // a resumed campaign continues from the existing output (`-i -`) instead of the seeds
     let (host_path_afl_out, dock_path_afl_out) = docked.wks_path("output");
-    let result = docker_run(
-        dock,
-        &docked.host_base,
-        vec![
-            "afl-fuzz".to_string(),
+    let fuzz_cmd = |index: usize| {
+        let seeds = match checkpoint {
+            None => docked.path_input.clone(),
+            Some(_) => "-".to_string(),
+        };
+        let mut args = vec![
             "-i".to_string(),
-            docked.path_input,
+            seeds,
             "-o".to_string(),
             dock_path_afl_out.clone(),
-            "--".to_string(),
-            dock_path_compiled,
-        ],
-        Some(TIMEOUT_FUZZ),
-    )?;
-    if !matches!(result, ExitStatus::Timeout) {
-        return Ok(ResultAFLpp {
-            completed: false,
-            num_crashes: 0,
+        ];
+        if instances > 1 {
+            if index == 0 {
+                args.extend(["-M".to_string(), "main".to_string()]);
+            } else {
+                args.extend(["-S".to_string(), format!("secondary{}", index)]);
+            }
+        }
+        if index == 0 {
+            args.extend(cmplog_args.iter().cloned());
+        }
+        args.extend(config.budget.instance_args(instances, checkpoint.as_ref()));
+        if let Some(seed) = config.seed {
+            args.extend([
+                "-s".to_string(),
+                seed.wrapping_add(index as u64).to_string(),
+            ]);
+        }
+        args.extend(fuzz_args.iter().cloned());
+        args.extend(["--".to_string(), dock_path_compiled.clone()]);
+        format!(
+            "env {} afl-fuzz {}",
+            fuzz_env.join(" "),
+            args.iter()
+                .map(|a| sh_quote(a))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    };
+    // every instance runs in the background and all of them are waited for, as under a budget of
+    // executions or core time each instance stops on its own once its share is used up
+    let launches: String = (0..instances)
+        .map(|i| {
+            let quiet = if i == 0 { "" } else { " >/dev/null 2>&1" };
+            format!("{}{} & pids=\"$pids $!\"; ", fuzz_cmd(i), quiet)
+        })
+        .collect();
+
+    // fuzz the program, mirroring the statistics files to a host-readable location
+    // (AFL++ creates its output directory and statistics files accessible only by root)
+    let (host_path_live, dock_path_live) = docked.wks_path(LIVE_STATS_DIR);
+    info!(
+        "fuzzing packet {} with {} AFL++ instance(s) on core(s) {:?}",
+        packet.id(),
+        instances,
+        cores
+    );
+    let script = format!(
+        "mkdir -p {live}; \
+         (while sleep {interval}; do \
+           for d in {out}/*/; do \
+             [ -d \"$d\" ] || continue; \
+             n=$(basename $d); mkdir -p {live}/$n; \
+             for f in fuzzer_stats plot_data; do \
+               [ -f $d$f ] && cp $d$f {live}/$n/$f.tmp && mv {live}/$n/$f.tmp {live}/$n/$f; \
+             done; \
+           done; \
+         done) & \
+         pids=; {launches}\
+         rc=0; for p in $pids; do wait $p || rc=$?; done; exit $rc",
+        live = dock_path_live,
+        interval = LIVE_STATS_INTERVAL.as_secs(),
+        out = dock_path_afl_out,
+        launches = launches,
+    );
+    let spent = checkpoint
+        .as_ref()
+        .map_or(Duration::ZERO, |c| c.wall_clock());
+    let remaining = config.budget.wall_clock_limit().saturating_sub(spent);
+    let host_workspace = docked.host_output.as_path();
+    let started = SystemTime::now();
+    let (stop_send, stop_recv) = mpsc::channel::<()>();
+    let result = thread::scope(|scope| {
+        scope.spawn(move || {
+            // the sender is dropped once fuzzing finishes
+            while let Err(mpsc::RecvTimeoutError::Timeout) =
+                stop_recv.recv_timeout(LIVE_STATS_INTERVAL)
+            {
+                let stats = match LiveStats::collect(&host_path_live) {
+                    None => continue,
+                    Some(stats) => stats,
+                };
+
+                // AFL++ carries its statistics over a resumption, only the clock restarts
+                let progress = Checkpoint {
+                    instances,
+                    wall_clock_secs: (spent + started.elapsed().unwrap_or_default()).as_secs(),
+                    core_time_secs: stats.core_time,
+                    executions: stats.execs_done,
+                };
+                if let Err(err) = progress.save(host_workspace) {
+                    warn!("unable to save fuzzing checkpoint: {}", err);
+                }
+                if let Err(err) = registry.publish_live(packet, stats) {
+                    warn!("unable to publish fuzzing statistics: {}", err);
+                }
+            }
         });
+
+        // the campaign may have been interrupted right before its end
+        if remaining.is_zero() {
+            drop(stop_send);
+            return Ok(ExitStatus::Timeout);
+        }
+        let mut binding = BTreeMap::new();
+        binding.insert(docked.host_base.as_path(), DOCKER_MNT.to_string());
+        let result = dock.sandbox_pinned(
+            DOCKER_TAG,
+            vec!["sh".to_string(), "-c".to_string(), script],
+            Some(remaining),
+            binding,
+            None,
+            cores,
+        );
+        drop(stop_send);
+        result
+    })?;
+    let wall_clock = spent + started.elapsed().unwrap_or_default();
+
+    // the campaign is over (or failed), there is nothing to resume anymore
+    let path_checkpoint = host_workspace.join(CHECKPOINT);
+    if path_checkpoint.exists() {
+        fs::remove_file(&path_checkpoint)?;
+    }
+
+    // a wall-clock campaign always runs into the timeout, while others end by themselves
+    // (or run into the cap, which is flagged as a short run below)
+    let finished = match config.budget {
+        Budget::WallClock => matches!(result, ExitStatus::Timeout),
+        Budget::Executions(_) | Budget::CoreTime(_) => {
+            matches!(result, ExitStatus::Success | ExitStatus::Timeout)
+        }
+    };
+    if !finished {
+        return Ok(failure());
     }
 
     // enable host access to the output directory
@@ -111,31 +776,115 @@ pub fn run_aflpp(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<Re
         None,
     )?;
 
-    // check number of crashes
-    let host_path_crash_dir = host_path_afl_out.join("default").join("crashes");
-    if !host_path_crash_dir.exists() {
-        bail!("unable to find the AFL++ crash directory on host system");
+    // merge the crashes and hangs found by all instances, de-duplicated by content
+    let crashes = match merge_findings(&host_path_afl_out, "crashes")? {
+        None => bail!("unable to find the AFL++ crash directory on host system"),
+        Some(found) => found,
+    };
+    let num_crashes = crashes.len() as u64;
+    let hangs = merge_findings(&host_path_afl_out, "hangs")?.unwrap_or_default();
+    let num_hangs = hangs.len() as u64;
+
+    // keep the hanging inputs around for download
+    let host_path_hangs = docked.host_base.join(HANGS_DIR);
+    if host_path_hangs.exists() {
+        fs::remove_dir_all(&host_path_hangs)?;
+    }
+    fs::create_dir_all(&host_path_hangs)?;
+    for (digest, path) in hangs {
+        fs::copy(
+            path,
+            host_path_hangs.join(format!("hang-{}", &digest[..16])),
+        )?;
     }
 
-    let mut num_crashes = 0;
-    for item in fs::read_dir(host_path_crash_dir)? {
-        let item = item?;
-        if item
-            .file_name()
-            .to_str()
-            .map_or(true, |s| s != "README.txt")
-        {
-            num_crashes += 1;
-        }
+    // collect campaign metrics
+    let metrics = MetricsAFLpp::collect_all(&host_path_afl_out);
+    if metrics.is_none() {
+        warn!("unable to collect AFL++ metrics for packet {}", packet.id());
+    }
+    let budget = BudgetReport::new(config.budget, wall_clock, metrics.as_ref());
+    if budget.short {
+        warn!("AFL++ campaign on packet {} ran short", packet.id());
     }
 
     // done with AFL++ fuzzing
     Ok(ResultAFLpp {
         completed: true,
         num_crashes,
+        num_hangs,
+        hangs_as_failures: assignment.hangs_as_failures,
+        metrics,
+        config: Some(config.clone()),
+        instances: Some(instances),
+        cores: Some(cores.to_vec()),
+        budget: Some(budget),
+        resumed,
     })
 }
 
+/// Collect the inputs in a findings directory (e.g., `crashes`) of every instance, keyed by the
+/// digest of their content, or `None` if no instance has such a directory
+fn merge_findings(afl_out: &Path, kind: &str) -> Result<Option<BTreeMap<String, PathBuf>>> {
+    let mut merged = BTreeMap::new();
+    let mut found = false;
+    for item in fs::read_dir(afl_out)? {
+        let dir = item?.path().join(kind);
+        if !dir.is_dir() {
+            continue;
+        }
+        found = true;
+        for item in fs::read_dir(dir)? {
+            let item = item?;
+            // e.g., `README.txt` in `crashes` and `.state` in `queue` are not inputs
+            if item.file_type()?.is_file()
+                && item.file_name().to_str().is_none_or(|s| s != "README.txt")
+                && !item.file_name().to_string_lossy().starts_with('.')
+            {
+                let digest = hex::encode(Sha3_256::digest(fs::read(item.path())?));
+                merged.entry(digest).or_insert_with(|| item.path());
+            }
+        }
+    }
+    Ok(found.then_some(merged))
+}
+
+/// Hanging inputs found by the AFL++ campaign on a packet, as file name and content
+pub(crate) fn hang_inputs(packet_path: &Path) -> Result<NamedFiles> {
+    let mut dir = packet_path.join(HANGS_DIR);
+    if !dir.is_dir() {
+        dir = workspace(packet_path).join(HANGS_DIR);
+    }
+    let mut inputs = vec![];
+    if !dir.is_dir() {
+        return Ok(inputs);
+    }
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        inputs.push((
+            item.file_name().to_string_lossy().to_string(),
+            fs::read(item.path())?,
+        ));
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// Inputs in the queues of all instances of the AFL++ campaign on a packet, keyed by the digest
+/// of their content
+pub(crate) fn queue_inputs(packet_path: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let afl_out = workspace(packet_path).join("output");
+    if !afl_out.is_dir() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(merge_findings(&afl_out, "queue")?.unwrap_or_default())
+}
+
+/// Decide how many AFL++ instances to run, sharing the cores among packets in flight
+pub(crate) fn plan_instances(registry: &Registry, cores: usize) -> usize {
+    (cores / registry.load().max(1)).clamp(1, MAX_FUZZ_INSTANCES)
+}
+
 /// Utility helper on invoking this Docker image
 fn docker_run(
     dock: &Dock,
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::packet::fmt_duration;
+
+/// Parse a percentage like `12.34%`
+fn parse_percent(v: &str) -> Option<f64> {
+    v.trim_end_matches('%').parse().ok()
+}
+
+/// Read the `key : value` table in the `fuzzer_stats` file of an instance
+fn read_fuzzer_stats(dir: &Path) -> Option<BTreeMap<String, String>> {
+    let text = fs::read_to_string(dir.join("fuzzer_stats")).ok()?;
+    let table = text
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    Some(table)
+}
+
+/// Look up an integer in the `fuzzer_stats` table, trying the keys of different AFL++ versions
+fn stats_int(table: &BTreeMap<String, String>, keys: &[&str]) -> u64 {
+    keys.iter()
+        .find_map(|k| table.get(*k).and_then(|v| v.parse().ok()))
+        .unwrap_or(0)
+}
+
+/// Look up a float (or a percentage) in the `fuzzer_stats` table
+fn stats_float(table: &BTreeMap<String, String>, key: &str) -> f64 {
+    table.get(key).and_then(|v| parse_percent(v)).unwrap_or(0.0)
+}
+
+/// A row of the `plot_data` file, i.e., a point in the coverage-over-time series
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlotPoint {
+    pub relative_time: u64,
+    pub execs_done: u64,
+    pub execs_per_sec: f64,
+    pub corpus_count: u64,
+    pub bitmap_cvg: f64,
+    pub edges_found: u64,
+    pub saved_crashes: u64,
+    pub saved_hangs: u64,
+}
+
+/// Read the rows of the `plot_data` file of an instance, using its header to locate columns
+fn read_plot_data(dir: &Path) -> Vec<PlotPoint> {
+    let text = match fs::read_to_string(dir.join("plot_data")) {
+        Ok(text) => text,
+        Err(_) => return vec![],
+    };
+    let mut lines = text.lines();
+    let header: Vec<_> = match lines.next().and_then(|l| l.strip_prefix('#')) {
+        None => return vec![],
+        Some(l) => l.split(',').map(|c| c.trim()).collect(),
+    };
+
+    let mut points = vec![];
+    for line in lines {
+        let row: BTreeMap<_, _> = header
+            .iter()
+            .copied()
+            .zip(line.split(',').map(|c| c.trim()))
+            .collect();
+        let int = |key: &str| row.get(key).and_then(|v| v.parse::<u64>().ok());
+        let float = |key: &str| row.get(key).and_then(|v| parse_percent(v));
+        let relative_time = match int("relative_time") {
+            None => continue,
+            Some(time) => time,
+        };
+        points.push(PlotPoint {
+            relative_time,
+            execs_done: int("total_execs").unwrap_or(0),
+            execs_per_sec: float("execs_per_sec").unwrap_or(0.0),
+            corpus_count: int("corpus_count").unwrap_or(0),
+            bitmap_cvg: float("map_size").unwrap_or(0.0),
+            edges_found: int("edges_found").unwrap_or(0),
+            saved_crashes: int("saved_crashes").unwrap_or(0),
+            saved_hangs: int("saved_hangs").unwrap_or(0),
+        });
+    }
+    points
+}
+
+/// Statistics of an AFL++ campaign in progress, aggregated over all instances
+#[derive(Clone, Default, Serialize)]
+pub struct LiveStats {
+    pub run_time: u64,
+    /// running time summed over all instances
+    pub core_time: u64,
+    pub execs_done: u64,
+    pub execs_per_sec: f64,
+    pub corpus_count: u64,
+    pub bitmap_cvg: f64,
+    pub edges_found: u64,
+    pub saved_crashes: u64,
+    pub saved_hangs: u64,
+}
+
+impl LiveStats {
+    pub fn to_human_readable(&self) -> String {
+        format!(
+            "{} execs ({:.0}/s), {} paths, {:.2}% coverage, {} crashes, {} hangs",
+            self.execs_done,
+            self.execs_per_sec,
+            self.corpus_count,
+            self.bitmap_cvg,
+            self.saved_crashes,
+            self.saved_hangs
+        )
+    }
+
+    /// Read the statistics of one instance from its `fuzzer_stats` and `plot_data` files
+    fn from_instance(dir: &Path) -> Option<Self> {
+        let mut found = None;
+
+        // `fuzzer_stats` has the complete picture but is only refreshed once in a while
+        if let Some(table) = read_fuzzer_stats(dir) {
+            found = Some(Self {
+                run_time: stats_int(&table, &["run_time"]),
+                core_time: stats_int(&table, &["run_time"]),
+                execs_done: stats_int(&table, &["execs_done"]),
+                execs_per_sec: stats_float(&table, "execs_per_sec"),
+                corpus_count: stats_int(&table, &["corpus_count", "paths_total"]),
+                bitmap_cvg: stats_float(&table, "bitmap_cvg"),
+                edges_found: stats_int(&table, &["edges_found"]),
+                saved_crashes: stats_int(&table, &["saved_crashes", "unique_crashes"]),
+                saved_hangs: stats_int(&table, &["saved_hangs", "unique_hangs"]),
+            });
+        }
+
+        // `plot_data` gets a new row more frequently, take it if it is fresher
+        if let Some(last) = read_plot_data(dir).pop() {
+            if found
+                .as_ref()
+                .is_none_or(|stats| last.relative_time >= stats.run_time)
+            {
+                found = Some(Self {
+                    run_time: last.relative_time,
+                    core_time: last.relative_time,
+                    execs_done: last.execs_done,
+                    execs_per_sec: last.execs_per_sec,
+                    corpus_count: last.corpus_count,
+                    bitmap_cvg: last.bitmap_cvg,
+                    edges_found: last.edges_found,
+                    saved_crashes: last.saved_crashes,
+                    saved_hangs: last.saved_hangs,
+                });
+            }
+        }
+
+        found
+    }
+
+    /// Aggregate the statistics of all instances mirrored in a directory
+    pub(crate) fn collect(dir: &Path) -> Option<Self> {
+        let mut total: Option<Self> = None;
+        for item in fs::read_dir(dir).ok()?.flatten() {
+            let stats = match Self::from_instance(&item.path()) {
+                None => continue,
+                Some(stats) => stats,
+            };
+            total = Some(match total {
+                None => stats,
+                Some(acc) => Self {
+                    run_time: acc.run_time.max(stats.run_time),
+                    core_time: acc.core_time + stats.core_time,
+                    execs_done: acc.execs_done + stats.execs_done,
+                    execs_per_sec: acc.execs_per_sec + stats.execs_per_sec,
+                    corpus_count: acc.corpus_count.max(stats.corpus_count),
+                    bitmap_cvg: acc.bitmap_cvg.max(stats.bitmap_cvg),
+                    edges_found: acc.edges_found.max(stats.edges_found),
+                    saved_crashes: acc.saved_crashes + stats.saved_crashes,
+                    saved_hangs: acc.saved_hangs + stats.saved_hangs,
+                },
+            });
+        }
+        total
+    }
+}
+
+/// Metrics of a finished AFL++ campaign
+#[derive(Serialize, Deserialize)]
+pub struct MetricsAFLpp {
+    pub run_time: u64,
+    /// running time summed over all instances
+    #[serde(default)]
+    pub core_time: u64,
+    pub execs_done: u64,
+    pub execs_per_sec: f64,
+    pub corpus_count: u64,
+    pub bitmap_cvg: f64,
+    pub stability: f64,
+    pub edges_found: u64,
+    pub saved_hangs: u64,
+    /// seconds into the campaign when the first crash was saved
+    pub time_to_first_crash: Option<u64>,
+    pub series: Vec<PlotPoint>,
+}
+
+impl MetricsAFLpp {
+    /// Collect the metrics of a campaign over all its instances after fuzzing
+    ///
+    /// Executions, throughput and hangs add up over instances, while corpus and coverage are
+    /// shared through syncing and hence take the maximum. The series is from the main instance.
+    pub(crate) fn collect_all(out: &Path) -> Option<Self> {
+        let mut instances = vec![];
+        for item in fs::read_dir(out).ok()?.flatten() {
+            let name = item.file_name().to_string_lossy().to_string();
+            if let Some(metrics) = Self::collect(&item.path()) {
+                instances.push((name, metrics));
+            }
+        }
+        instances.sort_by_key(|(name, _)| !matches!(name.as_str(), "main" | "default"));
+
+        let mut iter = instances.into_iter().map(|(_, metrics)| metrics);
+        let mut total = iter.next()?;
+        let mut count = 1.0;
+        for metrics in iter {
+            total.run_time = total.run_time.max(metrics.run_time);
+            total.core_time += metrics.core_time;
+            total.execs_done += metrics.execs_done;
+            total.execs_per_sec += metrics.execs_per_sec;
+            total.corpus_count = total.corpus_count.max(metrics.corpus_count);
+            total.bitmap_cvg = total.bitmap_cvg.max(metrics.bitmap_cvg);
+            total.stability += metrics.stability;
+            total.edges_found = total.edges_found.max(metrics.edges_found);
+            total.saved_hangs += metrics.saved_hangs;
+            total.time_to_first_crash =
+                match (total.time_to_first_crash, metrics.time_to_first_crash) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            count += 1.0;
+        }
+        total.stability /= count;
+        Some(total)
+    }
+
+    /// Collect the metrics of an instance after fuzzing
+    fn collect(dir: &Path) -> Option<Self> {
+        let table = read_fuzzer_stats(dir)?;
+        let series = read_plot_data(dir);
+
+        // crash files are named like `id:000000,sig:11,...,time:1234,...` (time in ms)
+        let from_names = fs::read_dir(dir.join("crashes"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|item| {
+                let name = item.file_name().into_string().ok()?;
+                name.split(',')
+                    .find_map(|field| field.strip_prefix("time:"))
+                    .and_then(|v| v.parse::<u64>().ok())
+            })
+            .min()
+            .map(|ms| ms / 1000);
+        // otherwise, approximate with the first plot row that sees a crash
+        let time_to_first_crash = from_names.or_else(|| {
+            series
+                .iter()
+                .find(|p| p.saved_crashes != 0)
+                .map(|p| p.relative_time)
+        });
+
+        let run_time = stats_int(&table, &["run_time"]);
+        Some(Self {
+            run_time,
+            core_time: run_time,
+            execs_done: stats_int(&table, &["execs_done"]),
+            execs_per_sec: stats_float(&table, "execs_per_sec"),
+            corpus_count: stats_int(&table, &["corpus_count", "paths_total"]),
+            bitmap_cvg: stats_float(&table, "bitmap_cvg"),
+            stability: stats_float(&table, "stability"),
+            edges_found: stats_int(&table, &["edges_found"]),
+            saved_hangs: stats_int(&table, &["saved_hangs", "unique_hangs"]),
+            time_to_first_crash,
+            series,
+        })
+    }
+
+    pub fn to_human_readable(&self) -> String {
+        let first_crash = match self.time_to_first_crash {
+            None => "n/a".to_string(),
+            Some(secs) => fmt_duration(Duration::from_secs(secs)),
+        };
+        [
+            format!(
+                "- executions: {} in {} ({:.0}/s)",
+                self.execs_done,
+                fmt_duration(Duration::from_secs(self.run_time)),
+                self.execs_per_sec
+            ),
+            format!("- corpus size: {}", self.corpus_count),
+            format!(
+                "- bitmap coverage: {:.2}% ({} edges)",
+                self.bitmap_cvg, self.edges_found
+            ),
+            format!("- stability: {:.2}%", self.stability),
+            format!("- time to first crash: {}", first_crash),
+        ]
+        .join("\n")
+    }
+
+    /// Export the coverage-over-time series as CSV
+    pub fn series_to_csv(&self) -> String {
+        let mut csv = String::from(
+            "relative_time,execs_done,execs_per_sec,corpus_count,bitmap_cvg,edges_found,saved_crashes,saved_hangs\n",
+        );
+        for p in &self.series {
+            csv.push_str(&format!(
+                "{},{},{:.2},{},{:.2},{},{},{}\n",
+                p.relative_time,
+                p.execs_done,
+                p.execs_per_sec,
+                p.corpus_count,
+                p.bitmap_cvg,
+                p.edges_found,
+                p.saved_crashes,
+                p.saved_hangs
+            ));
+        }
+        csv
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use tempdir::TempDir;
+
+use crate::assignment::Assignment;
+use crate::packet::{Packet, Registry};
+use crate::tool_aflpp::queue_inputs;
+use crate::tool_gcov::{run_test_batch_with, Mounts, TestOutcome, TIMEOUT_TEST_CASE};
+use crate::util_diff::{compare, CompareMode};
+use crate::util_docker::{Dock, ExitStatus};
+
+/// Tag of the Docker image (the GCOV one, which ships gcc)
+const DOCKER_TAG: &str = "gcov";
+
+/// Default mount point for work directory
+const DOCKER_MNT: &str = "/test";
+
+/// Mount point of the reference program and its outputs, never mounted with the program
+const DOCKER_MNT_REFERENCE: &str = "/reference";
+
+/// Maximum number of AFL++ queue entries replayed
+const MAX_QUEUE_ENTRIES: usize = 500;
+
+/// Maximum number of diverging inputs kept in the result
+const MAX_DIVERGENCES: usize = 20;
+
+/// Maximum number of bytes of a diverging input kept in the result
+const MAX_INPUT_PREVIEW: usize = 256;
+
+/// Maximum number of characters of an output shown in the report
+const MAX_OUTPUT_PREVIEW: usize = 200;
+
+/// An input on which the program and the reference program behave differently
+#[derive(Serialize, Deserialize)]
+pub struct Divergence {
+    /// where the input comes from, e.g., `input/hello` or `queue/default/id:000012,...`
+    pub origin: String,
+    /// content of the input, truncated to a limited length
+    pub input: String,
+    pub input_truncated: bool,
+    pub reference: TestOutcome,
+    pub program: TestOutcome,
+}
+
+/// Result for differential testing against the reference program
+#[derive(Serialize, Deserialize)]
+pub struct ResultDifferential {
+    pub compiled: bool,
+    pub reference_compiled: bool,
+    /// test cases under `input/` replayed
+    pub num_input_cases: usize,
+    /// distinct AFL++ queue entries replayed, not counting the ones identical to a test case
+    pub num_queue_entries: usize,
+    /// distinct AFL++ queue entries not replayed for being beyond the limit
+    pub num_queue_skipped: usize,
+    /// inputs not compared because the reference program does not finish on them
+    pub num_reference_timeouts: usize,
+    pub num_divergences: usize,
+    /// diverging inputs, up to a limited number
+    pub divergences: Vec<Divergence>,
+}
+
+impl ResultDifferential {
+    pub fn to_human_readable(&self) -> String {
+        let total = self.num_input_cases + self.num_queue_entries;
+        let verdict = if !self.reference_compiled {
+            "[failure] unable to compile the reference program, please report to the instructors"
+                .to_string()
+        } else if !self.compiled {
+            "[failure] unable to compile the program".to_string()
+        } else if self.num_divergences != 0 {
+            format!(
+                "[failure] {} out of {} input(s) diverge from the reference program",
+                self.num_divergences, total
+            )
+        } else {
+            format!(
+                "[success] all {} input(s) behave as with the reference program",
+                total
+            )
+        };
+        let mut lines = vec![verdict];
+        if !self.compiled || !self.reference_compiled {
+            return lines.join("\n");
+        }
+
+        lines.push(format!(
+            "- replayed: {} test case(s) under 'input/' and {} AFL++ queue entries",
+            self.num_input_cases, self.num_queue_entries
+        ));
+        if self.num_queue_skipped != 0 {
+            lines.push(format!(
+                "- not replayed: {} AFL++ queue entries beyond the limit of {}",
+                self.num_queue_skipped, MAX_QUEUE_ENTRIES
+            ));
+        }
+        if self.num_reference_timeouts != 0 {
+            lines.push(format!(
+                "- not compared: {} input(s) on which the reference program does not finish",
+                self.num_reference_timeouts
+            ));
+        }
+        for divergence in &self.divergences {
+            lines.push(format!(
+                "- {}: input {:?}{}",
+                divergence.origin,
+                divergence.input,
+                if divergence.input_truncated {
+                    " (truncated)"
+                } else {
+                    ""
+                }
+            ));
+            for (label, outcome) in [
+                ("reference", &divergence.reference),
+                ("program", &divergence.program),
+            ] {
+                lines.push(format!(
+                    "    {}: {}, output {}",
+                    label,
+                    outcome.to_human_readable(),
+                    preview(outcome)
+                ));
+            }
+        }
+        if self.num_divergences > self.divergences.len() {
+            lines.push(format!(
+                "- ... and {} more diverging input(s)",
+                self.num_divergences - self.divergences.len()
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Quoted beginning of the output of a run
+fn preview(outcome: &TestOutcome) -> String {
+    let text: String = outcome.stdout.chars().take(MAX_OUTPUT_PREVIEW).collect();
+    let truncated = outcome.stdout_truncated || outcome.stdout.chars().count() > MAX_OUTPUT_PREVIEW;
+    format!("{:?}{}", text, if truncated { " (truncated)" } else { "" })
+}
+
+/// Replay the test cases and the AFL++ queue against both the program and the reference program
+pub fn run_differential(
+    dock: &Dock,
+    registry: &Registry,
+    packet: &Packet,
+    assignment: &Assignment,
+    reference: &Path,
+) -> Result<ResultDifferential> {
+    let docked = registry.mk_dockerized_packet(packet, "differential", DOCKER_MNT)?;
+    let names = registry.test_names(packet)?;
+
+    let mut result = ResultDifferential {
+        compiled: false,
+        reference_compiled: false,
+        num_input_cases: 0,
+        num_queue_entries: 0,
+        num_queue_skipped: 0,
+        num_reference_timeouts: 0,
+        num_divergences: 0,
+        divergences: vec![],
+    };
+
+    // the reference program lives outside of the packet, so that the program can neither read
+    // it nor tamper with its outputs; it sees the packet read-only for the headers shipped there
+    let staged = TempDir::new("pap-reference")?;
+    fs::set_permissions(staged.path(), fs::Permissions::from_mode(0o777))?;
+    let mut mounts_reference = Mounts::new();
+    mounts_reference.insert(&docked.host_base, format!("{}:ro", DOCKER_MNT));
+    mounts_reference.insert(staged.path(), DOCKER_MNT_REFERENCE.to_string());
+
+    // compile the reference program, which may include the headers shipped with the packet
+    let host_path_reference_src = staged.path().join("reference.c");
+    fs::copy(reference, &host_path_reference_src)?;
+    fs::set_permissions(&host_path_reference_src, fs::Permissions::from_mode(0o644))?;
+    let dock_path_reference = format!("{}/reference", DOCKER_MNT_REFERENCE);
+    let status = dock.sandbox(
+        DOCKER_TAG,
+        vec![
+            "gcc".to_string(),
+            format!("-I{}", docked.path_base),
+            format!("{}/reference.c", DOCKER_MNT_REFERENCE),
+            "-o".to_string(),
+            dock_path_reference.clone(),
+        ],
+        None,
+        mounts_reference.clone(),
+        None,
+    )?;
+    if !matches!(status, ExitStatus::Success) {
+        return Ok(result);
+    }
+    result.reference_compiled = true;
+
+    // compile the program
+    let (_, dock_path_compiled) = docked.wks_path("main");
+    let status = docker_run(
+        dock,
+        &docked.host_base,
+        vec![
+            "gcc".to_string(),
+            docked.path_program.clone(),
+            "-o".to_string(),
+            dock_path_compiled.clone(),
+        ],
+        None,
+    )?;
+    if !matches!(status, ExitStatus::Success) {
+        return Ok(result);
+    }
+    result.compiled = true;
+
+    // gather the inputs under their indices: the test cases first, then the queue entries that
+    // are not identical to any of them (the queue starts with the test cases as seeds)
+    let (host_path_cases, dock_path_cases) = docked.wks_path("cases");
+    fs::create_dir(&host_path_cases)?;
+    let mut origins = vec![];
+    let mut seen = BTreeSet::new();
+    for test in &docked.path_input_cases {
+        let host_path = docked
+            .host_base
+            .join(Path::new(test).strip_prefix(&docked.path_base)?);
+        let content = fs::read(&host_path)?;
+        seen.insert(hex::encode(Sha3_256::digest(&content)));
+        origins.push((format!("input/{}", names.lookup(test)), content));
+    }
+    result.num_input_cases = origins.len();
+
+    let mut queue: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for (digest, path) in queue_inputs(&registry.packet_path(packet))? {
+        if seen.contains(&digest) {
+            continue;
+        }
+        // e.g., `queue/default/id:000012,...` for an entry in the queue of the `default` instance
+        let instance = path
+            .parent()
+            .and_then(|p| p.parent())
+            .and_then(|p| p.file_name())
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string());
+        let entry = path
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string());
+        queue.insert(format!("queue/{}/{}", instance, entry), fs::read(&path)?);
+    }
+    result.num_queue_skipped = queue.len().saturating_sub(MAX_QUEUE_ENTRIES);
+    result.num_queue_entries = queue.len() - result.num_queue_skipped;
+    origins.extend(queue.into_iter().take(MAX_QUEUE_ENTRIES));
+
+    for (i, (_, content)) in origins.iter().enumerate() {
+        let path = host_path_cases.join(i.to_string());
+        fs::write(&path, content)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+    }
+    if origins.is_empty() {
+        return Ok(result);
+    }
+
+    // replay the inputs against both programs, the program seeing the packet read-only but for
+    // where its own outcomes are recorded
+    let labels: Vec<_> = origins.iter().map(|(origin, _)| origin.clone()).collect();
+    let host_path_reference_runs = staged.path().join("runs");
+    fs::create_dir(&host_path_reference_runs)?;
+    fs::set_permissions(&host_path_reference_runs, fs::Permissions::from_mode(0o777))?;
+    let expected = run_test_batch_with(
+        dock,
+        mounts_reference,
+        (
+            &host_path_reference_runs,
+            &format!("{}/runs", DOCKER_MNT_REFERENCE),
+        ),
+        &dock_path_reference,
+        &dock_path_cases,
+        &labels,
+        TIMEOUT_TEST_CASE,
+    )?;
+
+    let (host_path_program_runs, dock_path_program_runs) = docked.wks_path("program-runs");
+    fs::create_dir(&host_path_program_runs)?;
+    fs::set_permissions(&host_path_program_runs, fs::Permissions::from_mode(0o777))?;
+    let mut mounts_program = Mounts::new();
+    mounts_program.insert(&docked.host_base, format!("{}:ro", DOCKER_MNT));
+    mounts_program.insert(
+        &host_path_program_runs,
+        format!("{}:rw", dock_path_program_runs),
+    );
+    let actual = run_test_batch_with(
+        dock,
+        mounts_program,
+        (&host_path_program_runs, &dock_path_program_runs),
+        &dock_path_compiled,
+        &dock_path_cases,
+        &labels,
+        TIMEOUT_TEST_CASE,
+    )?;
+
+    // the reference output is not a pattern, so only the whitespace mode is honored
+    let mode = match assignment.compare_output {
+        CompareMode::Whitespace => CompareMode::Whitespace,
+        _ => CompareMode::Exact,
+    };
+    for (((origin, content), (reference, expected)), (program, actual)) in
+        origins.into_iter().zip(expected).zip(actual)
+    {
+        if reference.timeout || (reference.exit_code.is_none() && reference.signal.is_none()) {
+            result.num_reference_timeouts += 1;
+            continue;
+        }
+        let same_status = (reference.exit_code, reference.signal, reference.timeout)
+            == (program.exit_code, program.signal, program.timeout);
+        let same_output = compare(
+            mode,
+            &String::from_utf8_lossy(&expected),
+            &String::from_utf8_lossy(&actual),
+        )
+        .is_none();
+        if same_status && same_output {
+            continue;
+        }
+        result.num_divergences += 1;
+        if result.divergences.len() < MAX_DIVERGENCES {
+            let kept = &content[..content.len().min(MAX_INPUT_PREVIEW)];
+            result.divergences.push(Divergence {
+                origin,
+                input: String::from_utf8_lossy(kept).to_string(),
+                input_truncated: content.len() > MAX_INPUT_PREVIEW,
+                reference,
+                program,
+            });
+        }
+    }
+
+    // done with differential testing
+    Ok(result)
+}
+
+/// Utility helper on invoking this Docker image
+fn docker_run(
+    dock: &Dock,
+    base: &Path,
+    cmd: Vec<String>,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus> {
+    let mut binding = BTreeMap::new();
+    binding.insert(base, DOCKER_MNT.to_string());
+    dock.sandbox(DOCKER_TAG, cmd, timeout, binding, None)
+}
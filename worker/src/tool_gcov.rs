@@ -1,5 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
 use std::fs::File;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -7,8 +9,11 @@ use anyhow::{bail, Result};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tempdir::TempDir;
 
-use crate::packet::{Packet, Registry};
+use crate::assignment::{Assignment, HiddenSuite};
+use crate::packet::{DockedPacket, Packet, Registry, DIR_EXPECTED};
+use crate::util_diff::{compare, CompareMode};
 use crate::util_docker::{Dock, ExitStatus};
 
 /// Tag of the Docker image
@@ -17,8 +22,26 @@ const DOCKER_TAG: &str = "gcov";
 /// Default mount point for work directory
 const DOCKER_MNT: &str = "/test";
 
+/// Mount point of the hidden test cases, only during hidden testing
+const DOCKER_MNT_HIDDEN: &str = "/hidden";
+
+/// Mounts of a container, as container paths (with options) by host path
+pub(crate) type Mounts<'a> = BTreeMap<&'a Path, String>;
+
 /// Timeout for testcase execution
-const TIMEOUT_TEST_CASE: Duration = Duration::from_secs(10);
+pub(crate) const TIMEOUT_TEST_CASE: Duration = Duration::from_secs(10);
+
+/// Extra time given to the container running a testcase, so that `timeout` reports first
+const TIMEOUT_CONTAINER_GRACE: Duration = Duration::from_secs(5);
+
+/// Exit code of `timeout` when the command times out
+const EXIT_CODE_TIMEOUT: i64 = 124;
+
+/// Maximum number of bytes of standard output kept for a testcase
+const MAX_TEST_STDOUT: usize = 1024;
+
+/// Maximum number of bytes of standard output compared against the expected output
+const MAX_COMPARED_STDOUT: usize = 64 * 1024;
 
 /// Path to the build directory
 static DOCKER_PATH: Lazy<PathBuf> = Lazy::new(|| {
@@ -34,6 +57,71 @@ pub fn provision(dock: &Dock, force: bool) -> Result<()> {
     Ok(())
 }
 
+/// Outcome of running the program on a test case
+#[derive(Serialize, Deserialize)]
+pub struct TestOutcome {
+    /// original name of the test case
+    pub name: String,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub timeout: bool,
+    pub duration_ms: Option<u64>,
+    /// standard output, truncated to a limited length
+    pub stdout: String,
+    pub stdout_truncated: bool,
+    /// comparison against the expected output, if there is one for the test case
+    #[serde(default)]
+    pub output_check: Option<OutputCheck>,
+}
+
+/// Comparison of the output of a test case against the expected one
+#[derive(Serialize, Deserialize)]
+pub struct OutputCheck {
+    pub mode: CompareMode,
+    /// differences from the expected output, `None` if it matches
+    pub diff: Option<String>,
+}
+
+impl TestOutcome {
+    /// Whether the program exits normally with status 0
+    pub fn is_success(&self) -> bool {
+        !self.timeout && self.exit_code == Some(0)
+    }
+
+    /// Whether the output does not match the expected one
+    pub fn is_mismatch(&self) -> bool {
+        self.output_check.as_ref().is_some_and(|c| c.diff.is_some())
+    }
+
+    /// Whether the program ends abnormally (killed by a signal or with a non-zero status), without
+    /// running into the timeout
+    pub fn is_crash(&self) -> bool {
+        !self.timeout && (self.signal.is_some() || self.exit_code.is_some_and(|c| c != 0))
+    }
+
+    /// Whether how the program ends is not recorded (e.g., the container is killed), which is
+    /// neither a success nor a crash, so that it never passes a test
+    pub fn is_unknown(&self) -> bool {
+        !self.timeout && self.signal.is_none() && self.exit_code.is_none()
+    }
+
+    pub(crate) fn to_human_readable(&self) -> String {
+        let outcome = if self.timeout {
+            "timeout".to_string()
+        } else {
+            match (self.signal, self.exit_code) {
+                (Some(signal), _) => format!("killed by {}", signal_name(signal)),
+                (None, Some(code)) => format!("exit code {}", code),
+                (None, None) => "unknown outcome".to_string(),
+            }
+        };
+        match self.duration_ms {
+            None => outcome,
+            Some(ms) => format!("{} after {} ms", outcome, ms),
+        }
+    }
+}
+
 /// Result for baseline evaluation
 #[derive(Serialize, Deserialize)]
 pub struct ResultBaseline {
@@ -42,24 +130,93 @@ pub struct ResultBaseline {
     pub input_fail: usize,
     pub crash_pass: usize,
     pub crash_fail: usize,
+    /// test cases under `input/` running into the timeout, not counted in `input_fail`
+    #[serde(default)]
+    pub input_timeouts: Vec<String>,
+    /// whether timeouts are counted as failures by the assignment
+    #[serde(default)]
+    pub hangs_as_failures: bool,
+    #[serde(default)]
+    pub input_cases: Vec<TestOutcome>,
+    #[serde(default)]
+    pub crash_cases: Vec<TestOutcome>,
 }
 
 impl ResultBaseline {
     pub fn to_human_readable(&self) -> String {
+        let mut lines = vec![self.verdict()];
+        if self.input_cases.is_empty() && !self.input_timeouts.is_empty() {
+            lines.push(format!(
+                "- running into the timeout: {}",
+                self.input_timeouts.join(", ")
+            ));
+        }
+        for case in self.input_cases.iter().filter(|c| !c.is_success()) {
+            lines.push(format!(
+                "- input/{}: {}",
+                case.name,
+                case.to_human_readable()
+            ));
+        }
+        for case in self.input_cases.iter() {
+            let check = match case.output_check.as_ref() {
+                Some(check) => check,
+                None => continue,
+            };
+            if let Some(diff) = check.diff.as_ref() {
+                lines.push(format!(
+                    "- input/{}: output differs from expected/{} ({} comparison)",
+                    case.name,
+                    case.name,
+                    check.mode.as_str()
+                ));
+                lines.extend(diff.lines().map(|l| format!("    {}", l)));
+            }
+        }
+        for case in self.crash_cases.iter().filter(|c| !c.is_crash()) {
+            lines.push(format!(
+                "- crash/{}: {}, expected a crash",
+                case.name,
+                case.to_human_readable()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn verdict(&self) -> String {
+        let input_total = self.input_pass + self.input_fail + self.input_timeouts.len();
         if !self.compiled {
             return "[failure] unable to compile the program".to_string();
         }
         if self.input_pass == 0 {
             return format!(
                 "[failure] none of the {} test case(s) under 'input/' directory executes successfully",
-                self.input_pass + self.input_fail,
+                input_total,
             );
         }
         if self.input_fail != 0 {
             return format!(
-                "[failure] {} out of {} test case(s) under 'input/' directory crash or timeout",
-                self.input_fail,
-                self.input_pass + self.input_fail
+                "[failure] {} out of {} test case(s) under 'input/' directory crash",
+                self.input_fail, input_total
+            );
+        }
+        if !self.input_timeouts.is_empty() && self.hangs_as_failures {
+            return format!(
+                "[failure] {} out of {} test case(s) under 'input/' directory timeout",
+                self.input_timeouts.len(),
+                input_total
+            );
+        }
+        let mismatches = self.input_cases.iter().filter(|c| c.is_mismatch()).count();
+        if mismatches != 0 {
+            let checked = self
+                .input_cases
+                .iter()
+                .filter(|c| c.output_check.is_some())
+                .count();
+            return format!(
+                "[failure] {} out of {} test case(s) under 'input/' directory print unexpected output",
+                mismatches, checked
             );
         }
         if self.crash_pass == 0 {
@@ -73,8 +230,15 @@ impl ResultBaseline {
 }
 
 /// Run user-provided test cases
-pub fn run_baseline(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<ResultBaseline> {
+pub fn run_baseline(
+    dock: &Dock,
+    registry: &Registry,
+    packet: &Packet,
+    assignment: &Assignment,
+) -> Result<ResultBaseline> {
+    let hangs_as_failures = assignment.hangs_as_failures;
     let docked = registry.mk_dockerized_packet(packet, "baseline", DOCKER_MNT)?;
+    let names = registry.test_names(packet)?;
 
     // compile the program
     let (_, dock_path_compiled) = docked.wks_path("main");
@@ -96,58 +260,63 @@ pub fn run_baseline(dock: &Dock, registry: &Registry, packet: &Packet) -> Result
             input_fail: 0,
             crash_pass: 0,
             crash_fail: 0,
+            input_timeouts: vec![],
+            hangs_as_failures,
+            input_cases: vec![],
+            crash_cases: vec![],
         });
     }
 
-    // run each tests in input directory
+    // run each tests in input directory, checking the output if it is expected to be something
     let mut input_pass = 0;
     let mut input_fail = 0;
-    for test in docked.path_input_cases.iter() {
-        let result = docker_run(
+    let mut input_timeouts = vec![];
+    let mut input_cases = vec![];
+    for test in by_index(&docked.path_input_cases) {
+        let (mut outcome, stdout) = run_test_case(
             dock,
-            &docked.host_base,
-            vec![
-                "bash".to_string(),
-                "-c".to_string(),
-                format!(
-                    "timeout {} {} < {}",
-                    TIMEOUT_TEST_CASE.as_secs(),
-                    dock_path_compiled,
-                    test
-                ),
-            ],
-            Some(TIMEOUT_TEST_CASE),
+            &docked,
+            &dock_path_compiled,
+            test,
+            names.lookup(test),
+            None,
+            None,
         )?;
-        if matches!(result, ExitStatus::Success) {
+        let host_path_expected = Path::new(test)
+            .file_name()
+            .map(|n| docked.host_base.join(DIR_EXPECTED).join(n));
+        if let Some(path) = host_path_expected.filter(|p| p.is_file()) {
+            outcome.output_check = Some(check_output(assignment.compare_output, &path, &stdout)?);
+        }
+        if outcome.timeout {
+            input_timeouts.push(outcome.name.clone());
+        } else if outcome.is_success() {
             input_pass += 1;
         } else {
             input_fail += 1;
         }
+        input_cases.push(outcome);
     }
 
     let mut crash_pass = 0;
     let mut crash_fail = 0;
-    for test in docked.path_crash_cases.iter() {
-        let result = docker_run(
+    let mut crash_cases = vec![];
+    for test in by_index(&docked.path_crash_cases) {
+        let (outcome, _) = run_test_case(
             dock,
-            &docked.host_base,
-            vec![
-                "bash".to_string(),
-                "-c".to_string(),
-                format!(
-                    "timeout {} {} < {}",
-                    TIMEOUT_TEST_CASE.as_secs(),
-                    dock_path_compiled,
-                    test
-                ),
-            ],
-            Some(TIMEOUT_TEST_CASE),
+            &docked,
+            &dock_path_compiled,
+            test,
+            names.lookup(test),
+            None,
+            None,
         )?;
-        if matches!(result, ExitStatus::Failure) {
+        if outcome.is_crash() {
             crash_pass += 1;
         } else {
             crash_fail += 1;
         }
+        crash_cases.push(outcome);
     }
 
     // done with baseline testing
@@ -157,6 +326,191 @@ pub fn run_baseline(dock: &Dock, registry: &Registry, packet: &Packet) -> Result
         input_fail,
         crash_pass,
         crash_fail,
+        input_timeouts,
+        hangs_as_failures,
+        input_cases,
+        crash_cases,
+    })
+}
+
+/// Result for the hidden test suite of an assignment
+#[derive(Serialize, Deserialize)]
+pub struct ResultHidden {
+    pub compiled: bool,
+    pub input_cases: Vec<TestOutcome>,
+    pub crash_cases: Vec<TestOutcome>,
+}
+
+impl ResultHidden {
+    /// Number of failing tests under `input/` and `crash/`
+    pub(crate) fn failures(&self) -> (usize, usize) {
+        let input = self
+            .input_cases
+            .iter()
+            .filter(|c| !c.is_success() || c.is_mismatch())
+            .count();
+        let crash = self.crash_cases.iter().filter(|c| !c.is_crash()).count();
+        (input, crash)
+    }
+
+    fn verdict(&self) -> String {
+        if !self.compiled {
+            return "[failure] unable to compile the program".to_string();
+        }
+        let (input, crash) = self.failures();
+        let total = self.input_cases.len() + self.crash_cases.len();
+        if input + crash != 0 {
+            return format!(
+                "[failure] {} out of {} hidden test case(s) failed",
+                input + crash,
+                total
+            );
+        }
+        format!("[success] all {} hidden test case(s) passed", total)
+    }
+
+    /// Summary shown to students, which does not reveal the test cases
+    pub fn to_summary(&self) -> String {
+        let mut lines = vec![self.verdict()];
+        if self.compiled {
+            let (input, crash) = self.failures();
+            lines.push(format!(
+                "- expected to run normally: {} out of {} passed",
+                self.input_cases.len() - input,
+                self.input_cases.len()
+            ));
+            lines.push(format!(
+                "- expected to crash: {} out of {} passed",
+                self.crash_cases.len() - crash,
+                self.crash_cases.len()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Full report for the staff, with every failing test case
+    pub fn to_human_readable(&self) -> String {
+        let mut lines = vec![self.verdict()];
+        for case in &self.input_cases {
+            if !case.is_success() {
+                lines.push(format!(
+                    "- input/{}: {}",
+                    case.name,
+                    case.to_human_readable()
+                ));
+            }
+            if let Some(check) = case.output_check.as_ref() {
+                if let Some(diff) = check.diff.as_ref() {
+                    lines.push(format!(
+                        "- input/{}: output differs from expected/{} ({} comparison)",
+                        case.name,
+                        case.name,
+                        check.mode.as_str()
+                    ));
+                    lines.extend(diff.lines().map(|l| format!("    {}", l)));
+                }
+            }
+        }
+        for case in self.crash_cases.iter().filter(|c| !c.is_crash()) {
+            lines.push(format!(
+                "- crash/{}: {}, expected a crash",
+                case.name,
+                case.to_human_readable()
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Run the hidden test suite of an assignment
+pub fn run_hidden(
+    dock: &Dock,
+    registry: &Registry,
+    packet: &Packet,
+    assignment: &Assignment,
+    suite: &HiddenSuite,
+) -> Result<ResultHidden> {
+    let docked = registry.mk_dockerized_packet(packet, "hidden", DOCKER_MNT)?;
+
+    // the hidden tests must not outlive this stage where later stages could read them, so the
+    // workspace (the only place the program may write to) goes away with whatever it holds
+    let result = hidden_runs(dock, &docked, assignment, suite);
+    fs::remove_dir_all(&docked.host_output)?;
+    result
+}
+
+/// Run the hidden tests, staged outside of the packet and mounted read-only, with the packet
+/// read-only as well but for the workspace of the stage
+fn hidden_runs(
+    dock: &Dock,
+    docked: &DockedPacket,
+    assignment: &Assignment,
+    suite: &HiddenSuite,
+) -> Result<ResultHidden> {
+    let staged = TempDir::new("pap-hidden")?;
+    fs::set_permissions(staged.path(), fs::Permissions::from_mode(0o755))?;
+    let mut mounts = Mounts::new();
+    mounts.insert(&docked.host_base, format!("{}:ro", DOCKER_MNT));
+    mounts.insert(&docked.host_output, format!("{}:rw", docked.path_output));
+    mounts.insert(staged.path(), format!("{}:ro", DOCKER_MNT_HIDDEN));
+
+    // compile the program
+    let (_, dock_path_compiled) = docked.wks_path("main");
+    let result = docker_run_with(
+        dock,
+        mounts.clone(),
+        vec![
+            "gcc".to_string(),
+            docked.path_program.clone(),
+            "-o".to_string(),
+            dock_path_compiled.clone(),
+        ],
+        None,
+    )?;
+    if !matches!(result, ExitStatus::Success) {
+        return Ok(ResultHidden {
+            compiled: false,
+            input_cases: vec![],
+            crash_cases: vec![],
+        });
+    }
+
+    // the tests are staged under their indices, as in a packet
+    let run_suite = |kind: &str, tests: &[(String, PathBuf)]| -> Result<Vec<_>> {
+        let host_path_tests = staged.path().join(format!("hidden-{}", kind));
+        let dock_path_tests = format!("{}/hidden-{}", DOCKER_MNT_HIDDEN, kind);
+        fs::create_dir(&host_path_tests)?;
+        fs::set_permissions(&host_path_tests, fs::Permissions::from_mode(0o755))?;
+        let mut cases = vec![];
+        for (i, (name, path)) in tests.iter().enumerate() {
+            let host_path_test = host_path_tests.join(i.to_string());
+            fs::copy(path, &host_path_test)?;
+            fs::set_permissions(&host_path_test, fs::Permissions::from_mode(0o644))?;
+            let (mut outcome, stdout) = run_test_case(
+                dock,
+                docked,
+                &dock_path_compiled,
+                &format!("{}/{}", dock_path_tests, i),
+                name.clone(),
+                None,
+                Some(&mounts),
+            )?;
+            if let Some(expected) = suite.expected.get(name) {
+                outcome.output_check =
+                    Some(check_output(assignment.compare_output, expected, &stdout)?);
+            }
+            cases.push(outcome);
+        }
+        Ok(cases)
+    };
+    let input_cases = run_suite("input", &suite.input)?;
+    let crash_cases = run_suite("crash", &suite.crash)?;
+
+    // done with hidden testing
+    Ok(ResultHidden {
+        compiled: true,
+        input_cases,
+        crash_cases,
     })
 }
 
@@ -166,6 +520,8 @@ pub struct ResultGcov {
     pub completed: bool,
     pub num_blocks: usize,
     pub cov_blocks: usize,
+    #[serde(default)]
+    pub cases: Vec<TestOutcome>,
 }
 
 impl ResultGcov {
@@ -185,6 +541,7 @@ impl ResultGcov {
 
 pub fn run_gcov(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<ResultGcov> {
     let docked = registry.mk_dockerized_packet(packet, "gcov", DOCKER_MNT)?;
+    let names = registry.test_names(packet)?;
 
     // compile the program
     let (_, dock_path_compiled) = docked.wks_path("main");
@@ -207,27 +564,23 @@ pub fn run_gcov(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<Res
             completed: false,
             num_blocks: 0,
             cov_blocks: 0,
+            cases: vec![],
         });
     }
 
     // run each tests in input directory
-    for test in docked.path_input_cases.iter() {
-        docker_run(
+    let mut cases = vec![];
+    for test in by_index(&docked.path_input_cases) {
+        let (outcome, _) = run_test_case(
             dock,
-            &docked.host_base,
-            vec![
-                "bash".to_string(),
-                "-c".to_string(),
-                format!(
-                    "cd {} && timeout {} {} < {}",
-                    docked.path_output,
-                    TIMEOUT_TEST_CASE.as_secs(),
-                    dock_path_compiled,
-                    test
-                ),
-            ],
+            &docked,
+            &dock_path_compiled,
+            test,
+            names.lookup(test),
+            Some(&docked.path_output),
             None,
         )?;
+        cases.push(outcome);
     }
 
     // calculate GCOV in json format
@@ -250,6 +603,7 @@ pub fn run_gcov(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<Res
             completed: false,
             num_blocks: 0,
             cov_blocks: 0,
+            cases: vec![],
         });
     }
     if !host_path_gcov_report.exists() {
@@ -268,9 +622,217 @@ pub fn run_gcov(dock: &Dock, registry: &Registry, packet: &Packet) -> Result<Res
         completed: true,
         num_blocks,
         cov_blocks,
+        cases,
     })
 }
 
+/// Test cases ordered by their indices (they are stored under their indices as names)
+fn by_index(cases: &BTreeSet<String>) -> Vec<&String> {
+    let mut sorted: Vec<_> = cases.iter().collect();
+    sorted.sort_by_key(|path| {
+        Path::new(path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.parse::<usize>().ok())
+    });
+    sorted
+}
+
+/// Compare the output of a test case against the expected output stored at a path
+pub(crate) fn check_output(
+    mode: CompareMode,
+    expected: &Path,
+    stdout: &[u8],
+) -> Result<OutputCheck> {
+    let diff = if stdout.len() > MAX_COMPARED_STDOUT {
+        Some(format!("output exceeds {} bytes", MAX_COMPARED_STDOUT))
+    } else {
+        compare(
+            mode,
+            &String::from_utf8_lossy(&fs::read(expected)?),
+            &String::from_utf8_lossy(stdout),
+        )
+    };
+    Ok(OutputCheck { mode, diff })
+}
+
+/// Run the compiled program on a test case (optionally in a working directory), recording its
+/// exit status, duration, and a truncated copy of its standard output, which is also returned
+/// with a larger limit
+fn run_test_case(
+    dock: &Dock,
+    docked: &DockedPacket,
+    binary: &str,
+    test: &str,
+    name: String,
+    cwd: Option<&str>,
+    mounts: Option<&Mounts>,
+) -> Result<(TestOutcome, Vec<u8>)> {
+    // e.g., `input-3` for the stored `input/3`
+    let path = Path::new(test);
+    let tag = format!(
+        "{}-{}",
+        path.parent()
+            .and_then(|p| p.file_name())
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string()),
+        path.file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string()),
+    );
+    let (host_path_status, dock_path_status) = docked.wks_path(&format!("{}.status", tag));
+    let (host_path_stdout, dock_path_stdout) = docked.wks_path(&format!("{}.stdout", tag));
+
+    // the output beyond the limit is drained rather than cut off, which would kill the program
+    let script = format!(
+        "{cd}s=$(date +%s%N); \
+         timeout {secs} {bin} < {test} | {{ head -c {limit} > {stdout}; cat > /dev/null; }}; \
+         rc=${{PIPESTATUS[0]}}; e=$(date +%s%N); \
+         echo $rc $(( (e - s) / 1000000 )) > {status}; exit $rc",
+        cd = cwd.map_or_else(String::new, |dir| format!("cd {} && ", dir)),
+        secs = TIMEOUT_TEST_CASE.as_secs(),
+        bin = binary,
+        test = test,
+        limit = MAX_COMPARED_STDOUT + 1,
+        stdout = dock_path_stdout,
+        status = dock_path_status,
+    );
+    let cmd = vec!["bash".to_string(), "-c".to_string(), script];
+    let timeout = Some(TIMEOUT_TEST_CASE + TIMEOUT_CONTAINER_GRACE);
+    let result = match mounts {
+        None => docker_run(dock, &docked.host_base, cmd, timeout)?,
+        Some(mounts) => docker_run_with(dock, mounts.clone(), cmd, timeout)?,
+    };
+    Ok(read_outcome(
+        name,
+        &host_path_status,
+        &host_path_stdout,
+        matches!(result, ExitStatus::Timeout),
+    ))
+}
+
+/// Run the compiled program on a batch of test cases in one container, the test cases being
+/// stored under their indices in a directory, and recording the outcomes as `run_test_case`
+/// does under a workspace directory named by the tag, each test case given the timeout
+pub(crate) fn run_test_batch(
+    dock: &Dock,
+    docked: &DockedPacket,
+    binary: &str,
+    tests: &str,
+    names: &[String],
+    timeout: Duration,
+    tag: &str,
+) -> Result<Vec<(TestOutcome, Vec<u8>)>> {
+    let (host_path_results, dock_path_results) = docked.wks_path(tag);
+    fs::create_dir(&host_path_results)?;
+    let mut mounts = Mounts::new();
+    mounts.insert(&docked.host_base, DOCKER_MNT.to_string());
+    run_test_batch_with(
+        dock,
+        mounts,
+        (&host_path_results, &dock_path_results),
+        binary,
+        tests,
+        names,
+        timeout,
+    )
+}
+
+/// Same as `run_test_batch` but in a container with the given mounts, recording the outcomes in
+/// the given directory (on the host and in the container), which must exist and be writable
+pub(crate) fn run_test_batch_with(
+    dock: &Dock,
+    mounts: Mounts,
+    results: (&Path, &str),
+    binary: &str,
+    tests: &str,
+    names: &[String],
+    timeout: Duration,
+) -> Result<Vec<(TestOutcome, Vec<u8>)>> {
+    let (host_path_results, dock_path_results) = results;
+    let script = format!(
+        "for i in $(seq 0 {last}); do s=$(date +%s%N); \
+         timeout {secs} {bin} < {tests}/$i | {{ head -c {limit} > {out}/$i.stdout; cat > /dev/null; }}; \
+         rc=${{PIPESTATUS[0]}}; e=$(date +%s%N); \
+         echo $rc $(( (e - s) / 1000000 )) > {out}/$i.status; done",
+        last = names.len() as i64 - 1,
+        secs = timeout.as_secs_f64(),
+        bin = binary,
+        tests = tests,
+        limit = MAX_COMPARED_STDOUT + 1,
+        out = dock_path_results,
+    );
+    // test cases left without a status when the container times out have an unknown outcome
+    docker_run_with(
+        dock,
+        mounts,
+        vec!["bash".to_string(), "-c".to_string(), script],
+        Some((timeout + Duration::from_secs(1)) * names.len() as u32 + TIMEOUT_CONTAINER_GRACE),
+    )?;
+    Ok(names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            read_outcome(
+                name.clone(),
+                &host_path_results.join(format!("{}.status", i)),
+                &host_path_results.join(format!("{}.stdout", i)),
+                false,
+            )
+        })
+        .collect())
+}
+
+/// Read the outcome of a test case from the status and standard output files left by the script
+/// running it, returning also the standard output with the larger limit
+fn read_outcome(
+    name: String,
+    host_path_status: &Path,
+    host_path_stdout: &Path,
+    container_timeout: bool,
+) -> (TestOutcome, Vec<u8>) {
+    // exit codes above 128 are the shell's way of reporting a signal
+    let status = fs::read_to_string(host_path_status).unwrap_or_default();
+    let mut fields = status.split_whitespace().map(|v| v.parse::<i64>().ok());
+    let code = fields.next().flatten();
+    let duration_ms = fields.next().flatten().map(|ms| ms.max(0) as u64);
+    let timeout = container_timeout || code == Some(EXIT_CODE_TIMEOUT);
+    let (exit_code, signal) = match code {
+        _ if timeout => (None, None),
+        Some(c @ 129..=192) => (None, Some((c - 128) as i32)),
+        Some(c) => (Some(c as i32), None),
+        None => (None, None),
+    };
+
+    let stdout = fs::read(host_path_stdout).unwrap_or_default();
+    let kept = &stdout[..stdout.len().min(MAX_TEST_STDOUT)];
+    let outcome = TestOutcome {
+        name,
+        exit_code,
+        signal,
+        timeout,
+        duration_ms,
+        stdout: String::from_utf8_lossy(kept).to_string(),
+        stdout_truncated: stdout.len() > MAX_TEST_STDOUT,
+        output_check: None,
+    };
+    (outcome, stdout)
+}
+
+/// Conventional name of a signal
+fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        15 => "SIGTERM",
+        _ => return format!("signal {}", signal),
+    };
+    name.to_string()
+}
+
 /// Utility helper on invoking this Docker image
 fn docker_run(
     dock: &Dock,
@@ -280,7 +842,17 @@ fn docker_run(
 ) -> Result<ExitStatus> {
     let mut binding = BTreeMap::new();
     binding.insert(base, DOCKER_MNT.to_string());
-    dock.sandbox(DOCKER_TAG, cmd, timeout, binding, None)
+    docker_run_with(dock, binding, cmd, timeout)
+}
+
+/// Same as `docker_run` but with the given mounts
+fn docker_run_with(
+    dock: &Dock,
+    mounts: Mounts,
+    cmd: Vec<String>,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus> {
+    dock.sandbox(DOCKER_TAG, cmd, timeout, mounts, None)
 }
 
 fn parse_gcov_json_report(v: &Value) -> Option<(usize, usize)> {
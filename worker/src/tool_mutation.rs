@@ -0,0 +1,730 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::assignment::Assignment;
+use crate::packet::{DockedPacket, Packet, Registry, DIR_EXPECTED};
+use crate::tool_gcov::{check_output, run_test_batch, TestOutcome, TIMEOUT_TEST_CASE};
+use crate::util_clex::{tokenize, Token, TokenKind};
+use crate::util_diff::CompareMode;
+use crate::util_docker::{Dock, ExitStatus};
+
+/// Tag of the Docker image (the GCOV one, which ships gcc)
+const DOCKER_TAG: &str = "gcov";
+
+/// Default mount point for work directory
+const DOCKER_MNT: &str = "/test";
+
+/// Maximum number of mutants an assignment may ask for
+const MAX_MUTANTS_LIMIT: usize = 1000;
+
+/// Timeout of a test case run against a mutant, relative to the slowest run of the program
+const MUTANT_TIMEOUT_FACTOR: u32 = 3;
+
+/// Shortest timeout of a test case run against a mutant
+const MIN_MUTANT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maximum number of surviving mutants listed in the report
+const MAX_SURVIVORS_SHOWN: usize = 30;
+
+/// Maximum number of characters of the mutated line kept for a mutant
+const MAX_CODE_PREVIEW: usize = 80;
+
+/// Operators swapped by the operator swap mutation, with their replacements
+const SWAPS: &[(&str, &str)] = &[
+    ("+", "-"),
+    ("-", "+"),
+    ("*", "/"),
+    ("/", "*"),
+    ("%", "*"),
+    ("<", "<="),
+    ("<=", "<"),
+    (">", ">="),
+    (">=", ">"),
+    ("==", "!="),
+    ("!=", "=="),
+    ("&&", "||"),
+    ("||", "&&"),
+    ("&", "|"),
+    ("|", "&"),
+    ("<<", ">>"),
+    (">>", "<<"),
+    ("+=", "-="),
+    ("-=", "+="),
+    ("++", "--"),
+    ("--", "++"),
+];
+
+/// Operators that are also unary (or a pointer declarator) when not following an operand
+const AMBIGUOUS_OPERATORS: &[&str] = &["+", "-", "*", "&"];
+
+/// Keywords after which an ambiguous operator is not a binary one, e.g., `return -1` or `int *p`
+const KEYWORDS: &[&str] = &[
+    "return", "sizeof", "case", "char", "short", "int", "long", "signed", "unsigned", "float",
+    "double", "void", "_Bool", "const", "volatile", "static", "extern", "register", "struct",
+    "union", "enum", "typedef",
+];
+
+/// Kinds of mutations applied to the program
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationOperator {
+    /// replace an arithmetic, relational, logical, or bitwise operator with a related one
+    OperatorSwap,
+    /// negate the condition of an `if` or a `while`
+    ConditionNegation,
+    /// shift an integer constant by one
+    ConstantTweak,
+}
+
+impl MutationOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OperatorSwap => "operator_swap",
+            Self::ConditionNegation => "condition_negation",
+            Self::ConstantTweak => "constant_tweak",
+        }
+    }
+}
+
+/// Mutation testing options of an assignment
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigMutation {
+    /// maximum number of mutants tested, sampled evenly over the program (`0`, the default,
+    /// skips the stage)
+    pub max_mutants: usize,
+    /// kinds of mutations to apply
+    pub operators: Vec<MutationOperator>,
+}
+
+impl Default for ConfigMutation {
+    fn default() -> Self {
+        Self {
+            max_mutants: 0,
+            operators: vec![
+                MutationOperator::OperatorSwap,
+                MutationOperator::ConditionNegation,
+                MutationOperator::ConstantTweak,
+            ],
+        }
+    }
+}
+
+impl ConfigMutation {
+    /// Check the options
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.max_mutants > MAX_MUTANTS_LIMIT {
+            bail!(
+                "too many mutants: {} (at most {})",
+                self.max_mutants,
+                MAX_MUTANTS_LIMIT
+            );
+        }
+        for (i, operator) in self.operators.iter().enumerate() {
+            if self.operators[..i].contains(operator) {
+                bail!("duplicated mutation operator: {}", operator.as_str());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A mutation of the program, as a replacement of a span of its source code
+struct Mutation {
+    operator: MutationOperator,
+    offset: usize,
+    len: usize,
+    replacement: String,
+    description: String,
+    line: usize,
+    column: usize,
+}
+
+impl Mutation {
+    /// Mutation replacing a token
+    fn on_token(operator: MutationOperator, token: &Token, replacement: String) -> Self {
+        Self {
+            operator,
+            offset: token.offset,
+            len: token.text.len(),
+            description: format!("'{}' -> '{}'", token.text, replacement),
+            replacement,
+            line: token.line,
+            column: token.column,
+        }
+    }
+
+    /// Source code of the mutant
+    fn apply(&self, src: &str) -> String {
+        format!(
+            "{}{}{}",
+            &src[..self.offset],
+            self.replacement,
+            &src[self.offset + self.len..]
+        )
+    }
+}
+
+/// Whether a token is an operand, or closes one, so that an operator after it is a binary one
+fn is_operand(token: Option<&Token>) -> bool {
+    match token {
+        None => false,
+        Some(token) => match token.kind {
+            TokenKind::Number | TokenKind::Str | TokenKind::Char => true,
+            TokenKind::Ident => !KEYWORDS.contains(&token.text),
+            TokenKind::Punct => matches!(token.text, ")" | "]"),
+        },
+    }
+}
+
+/// Parse an integer constant into its value, radix prefix, and suffix (octal constants and
+/// digit separators are left alone)
+fn parse_integer(text: &str) -> Option<(u64, &str, &str)> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let suffix = &text[digits.len()..];
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        return Some((u64::from_str_radix(hex, 16).ok()?, &digits[..2], suffix));
+    }
+    if digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+        || (digits.len() > 1 && digits.starts_with('0'))
+    {
+        return None;
+    }
+    Some((digits.parse().ok()?, "", suffix))
+}
+
+/// Index of the token closing the parenthesis opened at the given index
+fn matching_paren(tokens: &[Token], open: usize) -> Option<usize> {
+    if tokens.get(open)?.text != "(" {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.text {
+            "(" => depth += 1,
+            ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Generate the candidate mutations of a program, in source order
+fn generate(src: &str, operators: &[MutationOperator]) -> Vec<Mutation> {
+    let tokens = tokenize(src);
+    let mut mutations = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| &tokens[j]);
+        match token.kind {
+            TokenKind::Punct if operators.contains(&MutationOperator::OperatorSwap) => {
+                let swap = SWAPS.iter().find(|(from, _)| *from == token.text);
+                if let Some((_, to)) = swap {
+                    if !AMBIGUOUS_OPERATORS.contains(&token.text) || is_operand(prev) {
+                        mutations.push(Mutation::on_token(
+                            MutationOperator::OperatorSwap,
+                            token,
+                            to.to_string(),
+                        ));
+                    }
+                }
+            }
+            TokenKind::Number if operators.contains(&MutationOperator::ConstantTweak) => {
+                if let Some((value, prefix, suffix)) = parse_integer(token.text) {
+                    for tweaked in [value.checked_add(1), value.checked_sub(1)]
+                        .into_iter()
+                        .flatten()
+                    {
+                        let digits = if prefix.is_empty() {
+                            tweaked.to_string()
+                        } else {
+                            format!("{:x}", tweaked)
+                        };
+                        mutations.push(Mutation::on_token(
+                            MutationOperator::ConstantTweak,
+                            token,
+                            format!("{}{}{}", prefix, digits, suffix),
+                        ));
+                    }
+                }
+            }
+            TokenKind::Ident
+                if matches!(token.text, "if" | "while")
+                    && operators.contains(&MutationOperator::ConditionNegation) =>
+            {
+                if let Some(close) = matching_paren(&tokens, i + 1) {
+                    let open = tokens[i + 1].offset;
+                    let end = tokens[close].offset;
+                    mutations.push(Mutation {
+                        operator: MutationOperator::ConditionNegation,
+                        offset: open,
+                        len: end + 1 - open,
+                        replacement: format!("(!({}))", &src[open + 1..end]),
+                        description: format!("negated condition of '{}'", token.text),
+                        line: token.line,
+                        column: token.column,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    mutations
+}
+
+/// A mutant of the program
+#[derive(Serialize, Deserialize)]
+pub struct Mutant {
+    pub operator: MutationOperator,
+    /// what is mutated, e.g., `'<' -> '<='`
+    pub description: String,
+    pub line: usize,
+    pub column: usize,
+    /// the mutated line of the program, truncated to a limited length
+    pub code: String,
+}
+
+/// Result for mutation testing of the tests
+#[derive(Serialize, Deserialize)]
+pub struct ResultMutation {
+    pub compiled: bool,
+    /// candidate mutants generated from the program
+    pub num_candidates: usize,
+    /// mutants tested, sampled evenly from the candidates
+    pub num_tested: usize,
+    /// tested mutants that do not compile, not counted in the score
+    pub num_stillborn: usize,
+    /// tested mutants on which at least one test fails
+    pub num_killed: usize,
+    /// tests not used for failing on the program itself
+    pub unused_tests: Vec<String>,
+    /// tested mutants on which all tests pass
+    pub survivors: Vec<Mutant>,
+}
+
+impl ResultMutation {
+    /// Percentage of the compiling mutants killed by the tests, `None` if there is none
+    pub fn score(&self) -> Option<f64> {
+        let total = self.num_killed + self.survivors.len();
+        (self.compiled && total != 0).then(|| self.num_killed as f64 * 100.0 / total as f64)
+    }
+
+    pub fn to_human_readable(&self) -> String {
+        let total = self.num_killed + self.survivors.len();
+        let verdict = match self.score() {
+            _ if !self.compiled => "[failure] unable to compile the program".to_string(),
+            None => "[success] no mutant of the program to test".to_string(),
+            Some(_) if self.survivors.is_empty() => format!(
+                "[success] mutation score 100.00%, all {} mutant(s) killed by the tests",
+                total
+            ),
+            Some(score) => format!(
+                "[failure] mutation score {:.2}%, {} out of {} mutant(s) survive the tests",
+                score,
+                self.survivors.len(),
+                total
+            ),
+        };
+        let mut lines = vec![verdict];
+        if !self.compiled {
+            return lines.join("\n");
+        }
+
+        if self.num_tested < self.num_candidates {
+            lines.push(format!(
+                "- tested: {} out of {} mutant(s), sampled evenly over the program",
+                self.num_tested, self.num_candidates
+            ));
+        }
+        if self.num_stillborn != 0 {
+            lines.push(format!(
+                "- not counted: {} mutant(s) that do not compile",
+                self.num_stillborn
+            ));
+        }
+        if !self.unused_tests.is_empty() {
+            lines.push(format!(
+                "- not used: {} (failing on the program itself)",
+                self.unused_tests.join(", ")
+            ));
+        }
+        for mutant in self.survivors.iter().take(MAX_SURVIVORS_SHOWN) {
+            lines.push(format!(
+                "- main.c:{}:{}: {} survives in `{}`",
+                mutant.line, mutant.column, mutant.description, mutant.code
+            ));
+        }
+        if self.survivors.len() > MAX_SURVIVORS_SHOWN {
+            lines.push(format!(
+                "- ... and {} more surviving mutant(s)",
+                self.survivors.len() - MAX_SURVIVORS_SHOWN
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Tests of one kind in the packet
+struct Suite {
+    /// `input` or `crash`
+    kind: &'static str,
+    /// directory holding the tests under their indices
+    dir: String,
+    names: Vec<String>,
+    /// expected outputs of the tests, if any
+    expected: Vec<Option<PathBuf>>,
+}
+
+impl Suite {
+    fn load(registry: &Registry, packet: &Packet, docked: &DockedPacket) -> Result<Vec<Self>> {
+        let names = registry.test_names(packet)?;
+        let mut suites = vec![];
+        for (kind, dir, count) in [
+            ("input", &docked.path_input, docked.path_input_cases.len()),
+            ("crash", &docked.path_crash, docked.path_crash_cases.len()),
+        ] {
+            let expected = (0..count)
+                .map(|i| {
+                    let path = docked.host_base.join(DIR_EXPECTED).join(i.to_string());
+                    (kind == "input" && path.is_file()).then_some(path)
+                })
+                .collect();
+            suites.push(Self {
+                kind,
+                dir: dir.clone(),
+                names: (0..count)
+                    .map(|i| names.lookup(&format!("{}/{}", dir, i)))
+                    .collect(),
+                expected,
+            });
+        }
+        Ok(suites)
+    }
+
+    /// Whether a run of a test passes it, `None` if how the run ends is unknown
+    fn passes(
+        &self,
+        index: usize,
+        outcome: &TestOutcome,
+        stdout: &[u8],
+        mode: CompareMode,
+    ) -> Result<Option<bool>> {
+        if outcome.is_unknown() {
+            return Ok(None);
+        }
+        if self.kind == "crash" {
+            return Ok(Some(outcome.is_crash()));
+        }
+        if !outcome.is_success() {
+            return Ok(Some(false));
+        }
+        Ok(Some(match self.expected[index].as_ref() {
+            None => true,
+            Some(path) => check_output(mode, path, stdout)?.diff.is_none(),
+        }))
+    }
+
+    /// Run the tests against a binary, returning whether each of them passes (`None` if unknown)
+    /// and how long the slowest run takes
+    fn run(
+        &self,
+        dock: &Dock,
+        docked: &DockedPacket,
+        binary: &str,
+        timeout: Duration,
+        tag: &str,
+        mode: CompareMode,
+    ) -> Result<(Vec<Option<bool>>, Duration)> {
+        if self.names.is_empty() {
+            return Ok((vec![], Duration::ZERO));
+        }
+        let outcomes = run_test_batch(dock, docked, binary, &self.dir, &self.names, timeout, tag)?;
+
+        // the outputs are no longer needed once the outcomes are read
+        fs::remove_dir_all(docked.wks_path(tag).0)?;
+
+        let mut passed = vec![];
+        let mut slowest = Duration::ZERO;
+        for (i, (outcome, stdout)) in outcomes.iter().enumerate() {
+            passed.push(self.passes(i, outcome, stdout, mode)?);
+            slowest = slowest.max(Duration::from_millis(outcome.duration_ms.unwrap_or(0)));
+        }
+        Ok((passed, slowest))
+    }
+}
+
+/// Run the tests of the packet against mutants of the program, to tell how much of its behavior
+/// they actually check
+pub fn run_mutation(
+    dock: &Dock,
+    registry: &Registry,
+    packet: &Packet,
+    assignment: &Assignment,
+) -> Result<ResultMutation> {
+    let config = &assignment.mutation;
+    let mode = assignment.compare_output;
+    let docked = registry.mk_dockerized_packet(packet, "mutation", DOCKER_MNT)?;
+    let suites = Suite::load(registry, packet, &docked)?;
+
+    let mut result = ResultMutation {
+        compiled: false,
+        num_candidates: 0,
+        num_tested: 0,
+        num_stillborn: 0,
+        num_killed: 0,
+        unused_tests: vec![],
+        survivors: vec![],
+    };
+
+    // compile the program
+    let (_, dock_path_compiled) = docked.wks_path("main");
+    let status = docker_run(
+        dock,
+        &docked.host_base,
+        vec![
+            "gcc".to_string(),
+            docked.path_program.clone(),
+            "-o".to_string(),
+            dock_path_compiled.clone(),
+        ],
+        None,
+    )?;
+    if !matches!(status, ExitStatus::Success) {
+        return Ok(result);
+    }
+    result.compiled = true;
+
+    // only the tests passing on the program can tell a mutant apart
+    let mut usable = vec![];
+    let mut slowest = Duration::ZERO;
+    for suite in &suites {
+        let tag = format!("program-{}", suite.kind);
+        let (passed, duration) = suite.run(
+            dock,
+            &docked,
+            &dock_path_compiled,
+            TIMEOUT_TEST_CASE,
+            &tag,
+            mode,
+        )?;
+        for (name, ok) in suite.names.iter().zip(&passed) {
+            if *ok != Some(true) {
+                result.unused_tests.push(format!("{}/{}", suite.kind, name));
+            }
+        }
+        usable.push(
+            passed
+                .into_iter()
+                .map(|p| p == Some(true))
+                .collect::<Vec<_>>(),
+        );
+        slowest = slowest.max(duration);
+    }
+    let timeout = (slowest * MUTANT_TIMEOUT_FACTOR).clamp(MIN_MUTANT_TIMEOUT, TIMEOUT_TEST_CASE);
+
+    // generate the mutants, sampled evenly over the program if there are too many
+    let host_path_program = docked
+        .host_base
+        .join(Path::new(&docked.path_program).strip_prefix(&docked.path_base)?);
+    let src = fs::read_to_string(host_path_program)?;
+    let candidates = generate(&src, &config.operators);
+    result.num_candidates = candidates.len();
+    let sampled: Vec<_> = if candidates.len() <= config.max_mutants {
+        candidates.iter().collect()
+    } else {
+        (0..config.max_mutants)
+            .map(|i| &candidates[i * candidates.len() / config.max_mutants])
+            .collect()
+    };
+    result.num_tested = sampled.len();
+    if sampled.is_empty() {
+        return Ok(result);
+    }
+
+    // compile the mutants in one go, the headers being found next to the program
+    let (host_path_mutants, dock_path_mutants) = docked.wks_path("mutants");
+    fs::create_dir(&host_path_mutants)?;
+    for (i, mutation) in sampled.iter().enumerate() {
+        let path = host_path_mutants.join(format!("{}.c", i));
+        fs::write(&path, mutation.apply(&src))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+    }
+    let script = format!(
+        "for i in $(seq 0 {last}); do gcc -I{base} {dir}/$i.c -o {dir}/$i > /dev/null 2>&1; done",
+        last = sampled.len() - 1,
+        base = docked.path_base,
+        dir = dock_path_mutants,
+    );
+    docker_run(
+        dock,
+        &docked.host_base,
+        vec!["bash".to_string(), "-c".to_string(), script],
+        None,
+    )?;
+
+    // run the tests against each mutant until one of them fails
+    let lines: Vec<_> = src.lines().collect();
+    for (i, mutation) in sampled.into_iter().enumerate() {
+        let host_path_binary = host_path_mutants.join(i.to_string());
+        if !host_path_binary.is_file() {
+            result.num_stillborn += 1;
+            continue;
+        }
+        let binary = format!("{}/{}", dock_path_mutants, i);
+        let mut killed = false;
+        for (suite, usable) in suites.iter().zip(&usable) {
+            let tag = format!("mutant-{}-{}", i, suite.kind);
+            let (passed, _) = suite.run(dock, &docked, &binary, timeout, &tag, mode)?;
+            // a run whose outcome is unknown does not kill the mutant
+            if usable
+                .iter()
+                .zip(&passed)
+                .any(|(u, p)| *u && *p == Some(false))
+            {
+                killed = true;
+                break;
+            }
+        }
+        fs::remove_file(host_path_binary)?;
+
+        if killed {
+            result.num_killed += 1;
+            continue;
+        }
+        let line = lines.get(mutation.line - 1).map_or("", |l| l.trim());
+        let mut code: String = line.chars().take(MAX_CODE_PREVIEW).collect();
+        if line.chars().count() > MAX_CODE_PREVIEW {
+            code.push_str("...");
+        }
+        result.survivors.push(Mutant {
+            operator: mutation.operator,
+            description: mutation.description.clone(),
+            line: mutation.line,
+            column: mutation.column,
+            code,
+        });
+    }
+
+    // done with mutation testing
+    Ok(result)
+}
+
+/// Utility helper on invoking this Docker image
+fn docker_run(
+    dock: &Dock,
+    base: &Path,
+    cmd: Vec<String>,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus> {
+    let mut binding = BTreeMap::new();
+    binding.insert(base, DOCKER_MNT.to_string());
+    dock.sandbox(DOCKER_TAG, cmd, timeout, binding, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[MutationOperator] = &[
+        MutationOperator::OperatorSwap,
+        MutationOperator::ConditionNegation,
+        MutationOperator::ConstantTweak,
+    ];
+
+    /// Mutated lines of the program, one per mutation
+    fn mutants(src: &str, operators: &[MutationOperator]) -> Vec<String> {
+        generate(src, operators)
+            .iter()
+            .map(|m| {
+                let mutant = m.apply(src);
+                mutant.lines().nth(m.line - 1).unwrap().trim().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_integers() {
+        assert!(parse_integer("42") == Some((42, "", "")));
+        assert!(parse_integer("0") == Some((0, "", "")));
+        assert!(parse_integer("16UL") == Some((16, "", "UL")));
+        assert!(parse_integer("0x1F") == Some((31, "0x", "")));
+        assert!(parse_integer("0XffU") == Some((255, "0X", "U")));
+        assert!(parse_integer("010").is_none());
+        assert!(parse_integer("1'000").is_none());
+        assert!(parse_integer("1.5").is_none());
+        assert!(parse_integer("1e3").is_none());
+        assert!(parse_integer("0x").is_none());
+        assert!(parse_integer("99999999999999999999").is_none());
+    }
+
+    #[test]
+    fn matching_parens() {
+        let tokens = tokenize("if ((a) && f(b, (c))) x();");
+        assert_eq!(matching_paren(&tokens, 1), Some(14));
+        assert_eq!(matching_paren(&tokens, 2), Some(4));
+        assert_eq!(matching_paren(&tokens, 0), None);
+        let tokens = tokenize("while (a && (b)");
+        assert_eq!(matching_paren(&tokens, 1), None);
+    }
+
+    #[test]
+    fn swaps_binary_operators_only() {
+        let src = "int main(int argc, char **argv) {\n  int x = -argc, *p = &x;\n  return x * 2 - *p;\n}\n";
+        assert_eq!(
+            mutants(src, &[MutationOperator::OperatorSwap]),
+            ["return x / 2 - *p;", "return x * 2 + *p;"]
+        );
+    }
+
+    #[test]
+    fn negates_conditions() {
+        let src = "if (a < b) f();\nwhile ((n % 2) == 0) n /= 2;\ndo x++; while (x);\n";
+        assert_eq!(
+            mutants(src, &[MutationOperator::ConditionNegation]),
+            [
+                "if (!(a < b)) f();",
+                "while (!((n % 2) == 0)) n /= 2;",
+                "do x++; while (!(x));",
+            ]
+        );
+    }
+
+    #[test]
+    fn tweaks_constants() {
+        let src = "x = 0 + 7u + 0x1f + 010 + 1.5;\n";
+        assert_eq!(
+            mutants(src, &[MutationOperator::ConstantTweak]),
+            [
+                "x = 1 + 7u + 0x1f + 010 + 1.5;",
+                "x = 0 + 8u + 0x1f + 010 + 1.5;",
+                "x = 0 + 6u + 0x1f + 010 + 1.5;",
+                "x = 0 + 7u + 0x20 + 010 + 1.5;",
+                "x = 0 + 7u + 0x1e + 010 + 1.5;",
+            ]
+        );
+    }
+
+    #[test]
+    fn generates_in_source_order() {
+        let src = "// ça < va\nint f(int a) {\n  /* é */ if (a > 1) return a;\n  return 0;\n}\n";
+        let mutations = generate(src, ALL);
+        let locations: Vec<_> = mutations.iter().map(|m| (m.line, m.column)).collect();
+        assert_eq!(locations, [(3, 12), (3, 18), (3, 20), (3, 20), (4, 10)]);
+        assert_eq!(mutations[1].description, "'>' -> '>='");
+        assert!(generate(src, &[]).is_empty());
+    }
+}
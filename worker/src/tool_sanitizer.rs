@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::packet::{Packet, Registry};
+use crate::util_docker::{Dock, ExitStatus};
+
+/// Tag of the Docker image (the AFL++ one, which ships clang and its sanitizer runtimes)
+const DOCKER_TAG: &str = "afl";
+
+/// Default mount point for work directory
+const DOCKER_MNT: &str = "/test";
+
+/// Timeout for testcase execution
+const TIMEOUT_TEST_CASE: Duration = Duration::from_secs(10);
+
+/// Name of the program file, used to pick stack frames in the reports
+const PROGRAM_FILE: &str = "main.c";
+
+/// Sanitizers the program can be built with
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+    Memory,
+}
+
+impl Sanitizer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Address => "address",
+            Self::Undefined => "undefined",
+            Self::Memory => "memory",
+        }
+    }
+
+    /// Compiler flags enabling the sanitizer
+    fn cflags(&self) -> &'static [&'static str] {
+        match self {
+            Self::Address => &["-fsanitize=address"],
+            // keep going after an error so that all of them are reported
+            Self::Undefined => &["-fsanitize=undefined", "-fsanitize-recover=undefined"],
+            Self::Memory => &["-fsanitize=memory", "-fsanitize-memory-track-origins"],
+        }
+    }
+
+    /// Runtime options of the sanitizer, writing its reports under the given path prefix
+    fn options(&self, log_path: &str) -> String {
+        match self {
+            // leaks are not what this check is about, and LeakSanitizer is fragile in containers
+            Self::Address => format!("ASAN_OPTIONS=log_path={}:detect_leaks=0", log_path),
+            Self::Undefined => format!("UBSAN_OPTIONS=log_path={}:print_stacktrace=1", log_path),
+            Self::Memory => format!("MSAN_OPTIONS=log_path={}", log_path),
+        }
+    }
+}
+
+/// Sanitizer options of an assignment
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigSanitizer {
+    /// sanitizers to run the tests under `input/` with (none, the default, skips the stage)
+    pub sanitizers: Vec<Sanitizer>,
+}
+
+impl ConfigSanitizer {
+    /// Check the options
+    pub(crate) fn validate(&self) -> Result<()> {
+        for (i, sanitizer) in self.sanitizers.iter().enumerate() {
+            if self.sanitizers[..i].contains(sanitizer) {
+                bail!("duplicated sanitizer: {}", sanitizer.as_str());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Location in the program where a sanitizer reported an issue
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: Option<usize>,
+    pub function: Option<String>,
+}
+
+impl SourceLocation {
+    /// Parse a `file:line[:column]` string, only for locations in the program
+    fn parse(text: &str, function: Option<&str>) -> Option<Self> {
+        let mut parts = text.split(':');
+        let file = parts.next()?;
+        if !file.ends_with(PROGRAM_FILE) {
+            return None;
+        }
+        let line = parts.next()?.parse().ok()?;
+        let column = parts.next().and_then(|c| c.parse().ok());
+        Some(Self {
+            line,
+            column,
+            function: function.map(|f| f.to_string()),
+        })
+    }
+
+    fn to_human_readable(&self) -> String {
+        let mut text = format!("{}:{}", PROGRAM_FILE, self.line);
+        if let Some(column) = self.column {
+            text.push_str(&format!(":{}", column));
+        }
+        if let Some(function) = self.function.as_ref() {
+            text.push_str(&format!(" in {}", function));
+        }
+        text
+    }
+}
+
+/// An issue reported by a sanitizer, along with the tests that triggered it
+#[derive(Serialize, Deserialize)]
+pub struct SanitizerFinding {
+    pub sanitizer: Sanitizer,
+    /// class of the issue, e.g., `heap-buffer-overflow` or `signed integer overflow`
+    pub kind: String,
+    pub message: String,
+    pub location: Option<SourceLocation>,
+    pub tests: Vec<String>,
+}
+
+/// Outcome of running the tests under one sanitizer
+#[derive(Serialize, Deserialize)]
+pub struct SanitizerRun {
+    pub sanitizer: Sanitizer,
+    pub compiled: bool,
+    pub tests_run: usize,
+    pub tests_flagged: usize,
+}
+
+/// Result for sanitizer checks
+#[derive(Serialize, Deserialize)]
+pub struct ResultSanitizer {
+    pub runs: Vec<SanitizerRun>,
+    pub findings: Vec<SanitizerFinding>,
+}
+
+impl ResultSanitizer {
+    pub fn to_human_readable(&self) -> String {
+        let verdict = if self.runs.iter().any(|r| !r.compiled) {
+            "[failure] unable to build the program with all sanitizers".to_string()
+        } else if !self.findings.is_empty() {
+            format!(
+                "[failure] sanitizers reported {} issue(s)",
+                self.findings.len()
+            )
+        } else {
+            "[success] sanitizers reported no issues".to_string()
+        };
+        let mut lines = vec![verdict];
+        for run in &self.runs {
+            if run.compiled {
+                lines.push(format!(
+                    "- {}: {} out of {} test case(s) flagged",
+                    run.sanitizer.as_str(),
+                    run.tests_flagged,
+                    run.tests_run
+                ));
+            } else {
+                lines.push(format!("- {}: unable to compile", run.sanitizer.as_str()));
+            }
+        }
+        for finding in &self.findings {
+            let location = finding
+                .location
+                .as_ref()
+                .map_or("unknown location".to_string(), |l| l.to_human_readable());
+            lines.push(format!(
+                "- [{}] {} at {} (test: {})\n    {}",
+                finding.sanitizer.as_str(),
+                finding.kind,
+                location,
+                finding.tests.join(", "),
+                finding.message
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Run user-provided test cases under sanitizer-instrumented builds
+pub fn run_sanitizers(
+    dock: &Dock,
+    registry: &Registry,
+    packet: &Packet,
+    config: &ConfigSanitizer,
+) -> Result<ResultSanitizer> {
+    let docked = registry.mk_dockerized_packet(packet, "sanitizer", DOCKER_MNT)?;
+    let names = registry.test_names(packet)?;
+
+    let mut runs = vec![];
+    let mut findings: Vec<SanitizerFinding> = vec![];
+    for sanitizer in &config.sanitizers {
+        let name = sanitizer.as_str();
+
+        // compile the program
+        let (_, dock_path_compiled) = docked.wks_path(&format!("main.{}", name));
+        let mut cmd = vec![
+            "clang".to_string(),
+            "-g".to_string(),
+            "-O1".to_string(),
+            "-fno-omit-frame-pointer".to_string(),
+        ];
+        cmd.extend(sanitizer.cflags().iter().map(|f| f.to_string()));
+        cmd.extend([
+            docked.path_program.clone(),
+            "-o".to_string(),
+            dock_path_compiled.clone(),
+        ]);
+        let result = docker_run(dock, &docked.host_base, cmd, None)?;
+        if !matches!(result, ExitStatus::Success) {
+            runs.push(SanitizerRun {
+                sanitizer: *sanitizer,
+                compiled: false,
+                tests_run: 0,
+                tests_flagged: 0,
+            });
+            continue;
+        }
+
+        // run each tests in input directory, with the reports logged to a directory per test
+        let mut tests_flagged = 0;
+        for (i, test) in docked.path_input_cases.iter().enumerate() {
+            let (host_path_logs, dock_path_logs) = docked.wks_path(&format!("{}-{}", name, i));
+            fs::create_dir(&host_path_logs)?;
+            docker_run(
+                dock,
+                &docked.host_base,
+                vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    format!(
+                        "PATH=$PATH:$($LLVM_CONFIG --bindir) {} timeout {} {} < {}",
+                        sanitizer.options(&format!("{}/report", dock_path_logs)),
+                        TIMEOUT_TEST_CASE.as_secs(),
+                        dock_path_compiled,
+                        test
+                    ),
+                ],
+                Some(TIMEOUT_TEST_CASE),
+            )?;
+
+            // collect the reports
+            let test_name = names.lookup(test);
+            let mut flagged = false;
+            for item in fs::read_dir(&host_path_logs)? {
+                // the reports quote the program output, which may be anything
+                let content = fs::read(item?.path())?;
+                for (kind, message, location) in parse_report(&String::from_utf8_lossy(&content)) {
+                    flagged = true;
+                    match findings.iter_mut().find(|f| {
+                        f.sanitizer == *sanitizer && f.kind == kind && f.location == location
+                    }) {
+                        Some(existing) => {
+                            if !existing.tests.contains(&test_name) {
+                                existing.tests.push(test_name.clone());
+                            }
+                        }
+                        None => findings.push(SanitizerFinding {
+                            sanitizer: *sanitizer,
+                            kind,
+                            message,
+                            location,
+                            tests: vec![test_name.clone()],
+                        }),
+                    }
+                }
+            }
+            if flagged {
+                tests_flagged += 1;
+            }
+        }
+        runs.push(SanitizerRun {
+            sanitizer: *sanitizer,
+            compiled: true,
+            tests_run: docked.path_input_cases.len(),
+            tests_flagged,
+        });
+    }
+
+    // done with sanitizer checks
+    Ok(ResultSanitizer { runs, findings })
+}
+
+/// Parse a sanitizer log into the issues it reports, as kind, message, and location
+fn parse_report(content: &str) -> Vec<(String, String, Option<SourceLocation>)> {
+    let mut issues = vec![];
+    let mut lines = content.lines().peekable();
+    while let Some(line) = lines.next() {
+        // UBSan: `/test/main.c:12:5: runtime error: signed integer overflow: ...`
+        if let Some((prefix, message)) = line.split_once(": runtime error: ") {
+            let kind = message.split(':').next().unwrap_or(message).trim();
+            issues.push((
+                kind.to_string(),
+                message.trim().to_string(),
+                SourceLocation::parse(prefix.trim(), None),
+            ));
+            continue;
+        }
+
+        // ASan and MSan: `==42==ERROR: AddressSanitizer: heap-buffer-overflow on ...`
+        // followed by a stack trace, of which the first frame in the program is the location
+        let report = ["ERROR: AddressSanitizer: ", "WARNING: MemorySanitizer: "]
+            .iter()
+            .find_map(|marker| line.split_once(marker).map(|(_, rest)| rest));
+        let report = match report {
+            None => continue,
+            Some(rest) => rest.trim(),
+        };
+        let kind = report.split_whitespace().next().unwrap_or(report);
+
+        let mut location = None;
+        let mut in_stack = false;
+        while let Some(next) = lines.peek() {
+            let next = next.trim();
+            if next.starts_with('#') {
+                in_stack = true;
+                if location.is_none() {
+                    location = parse_frame(next);
+                }
+            } else if in_stack {
+                // only the first stack trace, the others are about allocation and origin
+                break;
+            }
+            lines.next();
+        }
+        issues.push((kind.to_string(), report.to_string(), location));
+    }
+    issues
+}
+
+/// Parse a stack frame like `#0 0x4c5b2a in foo /test/main.c:12:5`
+fn parse_frame(frame: &str) -> Option<SourceLocation> {
+    let mut parts = frame.split_whitespace().skip_while(|p| *p != "in").skip(1);
+    let function = parts.next()?;
+    SourceLocation::parse(parts.next()?, Some(function))
+}
+
+/// Utility helper on invoking this Docker image
+fn docker_run(
+    dock: &Dock,
+    base: &Path,
+    cmd: Vec<String>,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus> {
+    let mut binding = BTreeMap::new();
+    binding.insert(base, DOCKER_MNT.to_string());
+    dock.sandbox(DOCKER_TAG, cmd, timeout, binding, None)
+}
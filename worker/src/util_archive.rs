@@ -0,0 +1,482 @@
+use std::fs;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use flate2::read::GzDecoder;
+use tar::EntryType;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Maximum number of entries allowed in an archive
+const MAX_ENTRIES: usize = 1024;
+
+/// Maximum size of a single extracted file
+const MAX_ENTRY_SIZE: u64 = 64 * 1024;
+
+/// Maximum total size of all extracted files
+const MAX_TOTAL_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Maximum depth of an entry (an optional wrapper directory + directory + file)
+const MAX_DEPTH: usize = 3;
+
+/// Maximum ratio between the extracted size and the archive size
+const MAX_COMPRESSION_RATIO: u64 = 100;
+
+/// Extracted size below which the compression ratio is not checked
+const COMPRESSION_RATIO_GRACE: u64 = 64 * 1024;
+
+/// Maximum size of a decompressed tar stream (content plus headers and padding)
+const MAX_TAR_STREAM_SIZE: u64 = MAX_TOTAL_SIZE + (MAX_ENTRIES as u64) * 2048;
+
+/// File type bits in a unix mode
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+
+/// Kind of an entry in the archive
+enum EntryKind {
+    File,
+    Dir,
+}
+
+/// Where an entry in the package layout should go
+enum Placement {
+    Extract(PathBuf),
+    Skip,
+}
+
+/// A validating extractor that streams entries into a destination directory
+struct Extractor<'a> {
+    dst: &'a Path,
+    archive_size: u64,
+    num_entries: usize,
+    total_size: u64,
+    wrapper: Option<Option<String>>,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(dst: &'a Path, archive_size: u64) -> Self {
+        Self {
+            dst,
+            archive_size,
+            num_entries: 0,
+            total_size: 0,
+            wrapper: None,
+        }
+    }
+
+    /// Split an entry path into its normal components, rejecting anything suspicious
+    fn components(name: &str) -> Result<Vec<String>> {
+        if name.contains('\\') || name.contains('\0') {
+            bail!("invalid path in archive: {}", name);
+        }
+        let mut segs = vec![];
+        for comp in Path::new(name).components() {
+            match comp {
+                Component::Normal(seg) => match seg.to_str() {
+                    None => bail!("invalid path in archive: {}", name),
+                    Some(s) => segs.push(s.to_string()),
+                },
+                Component::CurDir => (),
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    bail!("path traversal in archive: {}", name);
+                }
+            }
+        }
+        if segs.len() > MAX_DEPTH {
+            bail!("path too deep in archive: {}", name);
+        }
+        Ok(segs)
+    }
+
+    /// Check whether the top-level name is part of the package layout
+    fn is_layout_name(name: &str) -> bool {
+        matches!(
+            name,
+            "main.c" | "interface.h" | "input" | "crash" | "expected"
+        ) || name.starts_with("README")
+            || name.starts_with(".gitignore")
+            || name.starts_with("output")
+    }
+
+    /// Decide where an entry goes in the package layout
+    fn locate(&mut self, name: &str, kind: &EntryKind) -> Result<Placement> {
+        let mut segs = Self::components(name)?;
+        if segs.is_empty() {
+            return match kind {
+                EntryKind::Dir => Ok(Placement::Skip),
+                EntryKind::File => bail!("empty path in archive"),
+            };
+        }
+
+        // decide on the wrapper directory upon the first meaningful entry
+        let wrapper = match &self.wrapper {
+            Some(w) => w.clone(),
+            None => {
+                let w = if Self::is_layout_name(&segs[0])
+                    || (segs.len() == 1 && matches!(kind, EntryKind::File))
+                {
+                    None
+                } else {
+                    Some(segs[0].clone())
+                };
+                self.wrapper = Some(w.clone());
+                w
+            }
+        };
+        if let Some(w) = wrapper {
+            if segs[0] != w {
+                bail!("unrecognized item: {}", name);
+            }
+            segs.remove(0);
+            if segs.is_empty() {
+                return match kind {
+                    EntryKind::Dir => Ok(Placement::Skip),
+                    EntryKind::File => bail!("unrecognized item: {}", name),
+                };
+            }
+        }
+
+        // check the layout
+        let head = segs[0].as_str();
+        if head.starts_with("output") {
+            return Ok(Placement::Skip);
+        }
+        match (head, segs.len(), kind) {
+            ("input" | "crash" | "expected", 1, EntryKind::Dir) => (),
+            ("input" | "crash" | "expected", 2, EntryKind::File) => (),
+            ("main.c" | "interface.h", 1, EntryKind::File) => (),
+            (n, 1, EntryKind::File) if n.starts_with("README") || n.starts_with(".gitignore") => (),
+            _ => bail!("unrecognized item: {}", name),
+        }
+        Ok(Placement::Extract(segs.into_iter().collect()))
+    }
+
+    /// Admit a new entry, returning the size budget left for it
+    fn admit(&mut self, declared_size: u64) -> Result<u64> {
+        self.num_entries += 1;
+        if self.num_entries > MAX_ENTRIES {
+            bail!("too many entries in archive");
+        }
+        if declared_size > MAX_ENTRY_SIZE {
+            bail!("archive entry too large");
+        }
+        Ok(MAX_ENTRY_SIZE.min(MAX_TOTAL_SIZE - self.total_size))
+    }
+
+    /// Stream a single entry into the destination
+    fn entry<R: Read>(
+        &mut self,
+        name: &str,
+        kind: EntryKind,
+        declared_size: u64,
+        reader: R,
+    ) -> Result<()> {
+        let budget = self.admit(declared_size)?;
+        let rel = match self.locate(name, &kind)? {
+            Placement::Skip => return Ok(()),
+            Placement::Extract(rel) => rel,
+        };
+        let path = self.dst.join(rel);
+
+        match kind {
+            EntryKind::Dir => fs::create_dir_all(&path)?,
+            EntryKind::File => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if path.exists() {
+                    bail!("duplicated entry in archive: {}", name);
+                }
+                let mut file = File::create(&path)?;
+                let copied = std::io::copy(&mut reader.take(budget + 1), &mut file)?;
+                if copied > budget {
+                    bail!("archive content exceeds the size limit");
+                }
+                self.total_size += copied;
+            }
+        }
+
+        // guard against zip bombs that slip under the size limits
+        if self.total_size > COMPRESSION_RATIO_GRACE
+            && self.total_size > self.archive_size.saturating_mul(MAX_COMPRESSION_RATIO)
+        {
+            bail!("archive compression ratio is suspiciously high");
+        }
+        Ok(())
+    }
+}
+
+/// Classify an entry based on its unix mode, rejecting symlinks and special files
+fn classify_mode(name: &str, mode: Option<u32>, is_dir: bool) -> Result<EntryKind> {
+    let kind = match mode.map(|m| m & S_IFMT) {
+        None | Some(0) => {
+            if is_dir {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            }
+        }
+        Some(S_IFREG) if !is_dir => EntryKind::File,
+        Some(S_IFDIR) => EntryKind::Dir,
+        Some(_) => bail!("symlink or special file in archive: {}", name),
+    };
+    Ok(kind)
+}
+
+/// Format of a submitted archive
+enum Format {
+    Zip,
+    TarGz,
+    Tar,
+}
+
+impl Format {
+    /// Detect the archive format by its magic bytes
+    fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+            return Some(Self::Zip);
+        }
+        if data.starts_with(&[0x1f, 0x8b]) {
+            return Some(Self::TarGz);
+        }
+        if data.len() >= 262 && &data[257..262] == b"ustar" {
+            return Some(Self::Tar);
+        }
+        None
+    }
+}
+
+/// Safely extract an archive (ZIP, tar, or tar.gz) into the destination directory
+pub fn extract(data: &[u8], dst: &Path) -> Result<()> {
+    match Format::detect(data) {
+        None => bail!("unrecognized archive format, expect a ZIP, tar, or tar.gz archive"),
+        Some(Format::Zip) => extract_zip(data, dst),
+        Some(Format::TarGz) => extract_tar(GzDecoder::new(data), data.len() as u64, dst),
+        Some(Format::Tar) => extract_tar(data, data.len() as u64, dst),
+    }
+}
+
+/// Safely extract a tar stream into the destination directory
+fn extract_tar<R: Read>(reader: R, archive_size: u64, dst: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader.take(MAX_TAR_STREAM_SIZE));
+    let mut extractor = Extractor::new(dst, archive_size);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let kind = match header.entry_type() {
+            EntryType::Regular | EntryType::Continuous => EntryKind::File,
+            EntryType::Directory => EntryKind::Dir,
+            EntryType::XGlobalHeader => continue,
+            _ => bail!("symlink or special file in archive: {:?}", entry.path()?),
+        };
+        let name = match entry.path()?.to_str() {
+            None => bail!("invalid path in archive: {:?}", entry.path()?),
+            Some(n) => n.to_string(),
+        };
+        let size = header.size()?;
+        extractor.entry(&name, kind, size, entry)?;
+    }
+    Ok(())
+}
+
+/// Safely extract a ZIP archive into the destination directory
+fn extract_zip(data: &[u8], dst: &Path) -> Result<()> {
+    let mut zip = ZipArchive::new(Cursor::new(data))?;
+    if zip.len() > MAX_ENTRIES {
+        bail!("too many entries in archive");
+    }
+
+    let mut extractor = Extractor::new(dst, data.len() as u64);
+    for i in 0..zip.len() {
+        let file = zip.by_index(i)?;
+        let name = file.name().to_string();
+        if file.enclosed_name().is_none() {
+            bail!("path traversal in archive: {}", name);
+        }
+        let kind = classify_mode(&name, file.unix_mode(), file.is_dir())?;
+        let size = file.size();
+        extractor.entry(&name, kind, size, file)?;
+    }
+    Ok(())
+}
+
+/// Files given as name and content
+pub type NamedFiles = Vec<(String, Vec<u8>)>;
+
+/// Pack files into a ZIP archive in memory
+pub fn pack_zip(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (name, content) in files {
+        zip.start_file(name.as_str(), SimpleFileOptions::default())?;
+        zip.write_all(content)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use tar::{Builder, Header};
+    use tempdir::TempDir;
+    use zip::CompressionMethod;
+
+    use super::*;
+
+    /// Build a ZIP archive from files given as name and content
+    fn zip_of(files: &[(&str, &[u8])], method: CompressionMethod) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let options = SimpleFileOptions::default().compression_method(method);
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Build a tar archive from entries given as type, raw name, and content
+    fn tar_of(entries: &[(EntryType, &str, &[u8])]) -> Vec<u8> {
+        let mut tarball = Builder::new(vec![]);
+        for (kind, name, content) in entries {
+            // the name is set raw, as the builder refuses suspicious paths
+            let mut header = Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*kind);
+            header.set_mode(0o644);
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            tarball.append(&header, *content).unwrap();
+        }
+        tarball.into_inner().unwrap()
+    }
+
+    fn files(n: usize, size: usize) -> Vec<(String, Vec<u8>)> {
+        // content that does not compress, so that only the size limits are at play
+        let mut state = 1u32;
+        (0..n)
+            .map(|i| {
+                let content = (0..size)
+                    .map(|_| {
+                        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                        (state >> 16) as u8
+                    })
+                    .collect();
+                (format!("input/{}", i), content)
+            })
+            .collect()
+    }
+
+    fn extract_err(data: &[u8]) -> String {
+        let dst = TempDir::new("pap-archive").unwrap();
+        extract(data, dst.path()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn well_formed() {
+        let data = zip_of(
+            &[
+                ("pkt/main.c", b"int main() {}"),
+                ("pkt/input/a", b"1"),
+                ("pkt/output/junk", b"x"),
+            ],
+            CompressionMethod::Deflated,
+        );
+        let dst = TempDir::new("pap-archive").unwrap();
+        extract(&data, dst.path()).unwrap();
+        assert_eq!(
+            fs::read(dst.path().join("main.c")).unwrap(),
+            b"int main() {}"
+        );
+        assert_eq!(fs::read(dst.path().join("input/a")).unwrap(), b"1");
+        assert!(!dst.path().join("output").exists());
+
+        let data = tar_of(&[(EntryType::Regular, "crash/b", b"2")]);
+        let dst = TempDir::new("pap-archive").unwrap();
+        extract(&data, dst.path()).unwrap();
+        assert_eq!(fs::read(dst.path().join("crash/b")).unwrap(), b"2");
+    }
+
+    #[test]
+    fn path_traversal() {
+        for name in ["../x", "input/../../x"] {
+            let data = zip_of(&[(name, b"x")], CompressionMethod::Stored);
+            assert!(extract_err(&data).contains("path traversal"), "{}", name);
+            let data = tar_of(&[(EntryType::Regular, name, b"x")]);
+            assert!(extract_err(&data).contains("path traversal"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn absolute_paths() {
+        let data = zip_of(&[("/main.c", b"x")], CompressionMethod::Stored);
+        assert!(extract_err(&data).contains("path traversal"));
+        let data = tar_of(&[(EntryType::Regular, "/main.c", b"x")]);
+        assert!(extract_err(&data).contains("path traversal"));
+    }
+
+    #[test]
+    fn symlinks() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.add_symlink("main.c", "/etc/passwd", SimpleFileOptions::default())
+            .unwrap();
+        let data = zip.finish().unwrap().into_inner();
+        assert!(extract_err(&data).contains("symlink"));
+
+        let data = tar_of(&[(EntryType::Symlink, "main.c", b"")]);
+        assert!(extract_err(&data).contains("symlink"));
+    }
+
+    fn entries_of(files: &[(String, Vec<u8>)]) -> Vec<(&str, &[u8])> {
+        files
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_slice()))
+            .collect()
+    }
+
+    #[test]
+    fn total_size() {
+        // just one file over the limit, in entries that are each within it
+        let n = (MAX_TOTAL_SIZE / MAX_ENTRY_SIZE) as usize + 1;
+        let files = files(n, MAX_ENTRY_SIZE as usize);
+        let data = zip_of(&entries_of(&files), CompressionMethod::Stored);
+        assert!(extract_err(&data).contains("size limit"));
+
+        let entries: Vec<_> = entries_of(&files)
+            .into_iter()
+            .map(|(name, content)| (EntryType::Regular, name, content))
+            .collect();
+        let data = tar_of(&entries);
+        assert!(extract_err(&data).contains("size limit"));
+
+        // but one file fewer fits
+        let data = zip_of(&entries_of(&files[1..]), CompressionMethod::Stored);
+        let dst = TempDir::new("pap-archive").unwrap();
+        extract(&data, dst.path()).unwrap();
+    }
+
+    #[test]
+    fn compression_ratio() {
+        let zeros = vec![0; MAX_ENTRY_SIZE as usize];
+        let data = zip_of(
+            &[("input/a", &zeros), ("input/b", &zeros)],
+            CompressionMethod::Deflated,
+        );
+        assert!(extract_err(&data).contains("compression ratio"));
+    }
+
+    #[test]
+    fn entry_count() {
+        let names: Vec<_> = (0..=MAX_ENTRIES).map(|i| format!("input/{}", i)).collect();
+        let files: Vec<_> = names.iter().map(|n| (n.as_str(), &b""[..])).collect();
+        let data = zip_of(&files, CompressionMethod::Stored);
+        assert!(extract_err(&data).contains("too many entries"));
+
+        let entries: Vec<_> = files
+            .into_iter()
+            .map(|(name, content)| (EntryType::Regular, name, content))
+            .collect();
+        let data = tar_of(&entries);
+        assert!(extract_err(&data).contains("too many entries"));
+    }
+}
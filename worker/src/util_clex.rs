@@ -0,0 +1,334 @@
+/// Kinds of tokens in C source code
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Ident,
+    Number,
+    Str,
+    Char,
+    Punct,
+}
+
+/// A token in C source code, with its location
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// byte offset of the token in the source
+    pub offset: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number (in bytes)
+    pub column: usize,
+}
+
+/// Punctuators with more than one character, longest first
+const MULTI_CHAR_PUNCTS: &[&str] = &[
+    "<<=", ">>=", "...", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=",
+    "/=", "%=", "+=", "-=", "&=", "^=", "|=", "##",
+];
+
+/// Prefixes of wide and unicode string or character literals
+const LITERAL_PREFIXES: &[&str] = &["u8", "u", "U", "L"];
+
+/// Split C source code into tokens, skipping comments and preprocessor directives
+pub fn tokenize(src: &str) -> Vec<Token<'_>> {
+    let bytes = src.as_bytes();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    let mut line = 1;
+    let mut line_start = 0;
+    // whether only whitespace has been seen on the current line
+    let mut at_line_start = true;
+
+    while pos < bytes.len() {
+        let c = bytes[pos];
+
+        // whitespace
+        if c == b'\n' {
+            pos += 1;
+            line += 1;
+            line_start = pos;
+            at_line_start = true;
+            continue;
+        }
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if c == b'\\' && bytes.get(pos + 1) == Some(&b'\n') {
+            pos += 2;
+            line += 1;
+            line_start = pos;
+            continue;
+        }
+
+        // comments (compared as bytes, as comments may hold any character)
+        if bytes[pos..].starts_with(b"//") {
+            while pos < bytes.len() && bytes[pos] != b'\n' {
+                if bytes[pos] == b'\\' && bytes.get(pos + 1) == Some(&b'\n') {
+                    pos += 1;
+                    line += 1;
+                    line_start = pos + 1;
+                }
+                pos += 1;
+            }
+            continue;
+        }
+        if bytes[pos..].starts_with(b"/*") {
+            pos += 2;
+            while pos < bytes.len() && !bytes[pos..].starts_with(b"*/") {
+                if bytes[pos] == b'\n' {
+                    line += 1;
+                    line_start = pos + 1;
+                }
+                pos += 1;
+            }
+            pos = (pos + 2).min(bytes.len());
+            continue;
+        }
+
+        // preprocessor directives, including line continuations
+        if c == b'#' && at_line_start {
+            while pos < bytes.len() && bytes[pos] != b'\n' {
+                if bytes[pos] == b'\\' && bytes.get(pos + 1) == Some(&b'\n') {
+                    pos += 1;
+                    line += 1;
+                    line_start = pos + 1;
+                }
+                pos += 1;
+            }
+            continue;
+        }
+        at_line_start = false;
+
+        let start = pos;
+        let column = start - line_start + 1;
+        let kind = if c == b'"' || c == b'\'' {
+            pos = skip_literal(bytes, pos);
+            if c == b'"' {
+                TokenKind::Str
+            } else {
+                TokenKind::Char
+            }
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+            // prefixed literals like L"..." or u8"..."
+            match bytes.get(pos) {
+                Some(&q @ (b'"' | b'\'')) if LITERAL_PREFIXES.contains(&&src[start..pos]) => {
+                    pos = skip_literal(bytes, pos);
+                    if q == b'"' {
+                        TokenKind::Str
+                    } else {
+                        TokenKind::Char
+                    }
+                }
+                _ => TokenKind::Ident,
+            }
+        } else if c.is_ascii_digit()
+            || (c == b'.' && bytes.get(pos + 1).is_some_and(|n| n.is_ascii_digit()))
+        {
+            while pos < bytes.len() {
+                let n = bytes[pos];
+                let exponent_sign =
+                    matches!(n, b'+' | b'-') && matches!(bytes[pos - 1], b'e' | b'E' | b'p' | b'P');
+                if exponent_sign
+                    || n.is_ascii_alphanumeric()
+                    || n == b'_'
+                    || n == b'.'
+                    || n == b'\''
+                {
+                    pos += 1;
+                } else {
+                    break;
+                }
+            }
+            TokenKind::Number
+        } else {
+            match MULTI_CHAR_PUNCTS
+                .iter()
+                .find(|p| src[pos..].starts_with(*p))
+            {
+                Some(p) => pos += p.len(),
+                // keep multi-byte characters in one piece
+                None => pos += src[pos..].chars().next().map_or(1, |ch| ch.len_utf8()),
+            }
+            TokenKind::Punct
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &src[start..pos],
+            offset: start,
+            line,
+            column,
+        });
+
+        // literals may span lines through line continuations
+        for (i, b) in bytes[start..pos].iter().enumerate() {
+            if *b == b'\n' {
+                line += 1;
+                line_start = start + i + 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Skip over a string or character literal starting at the opening quote
+fn skip_literal(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut pos = start + 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'\n' => return pos,
+            c if c == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Decode the content of a string or character literal token into raw bytes
+pub fn unescape(literal: &str) -> Vec<u8> {
+    // strip the prefix and the quotes
+    let body = match literal.find(['"', '\'']) {
+        None => return vec![],
+        Some(i) => &literal[i + 1..],
+    };
+    let body = body.strip_suffix(['"', '\'']).unwrap_or(body).as_bytes();
+
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < body.len() {
+        if body[pos] != b'\\' || pos + 1 == body.len() {
+            out.push(body[pos]);
+            pos += 1;
+            continue;
+        }
+        pos += 1;
+        let c = body[pos];
+        pos += 1;
+        match c {
+            b'n' => out.push(b'\n'),
+            b't' => out.push(b'\t'),
+            b'r' => out.push(b'\r'),
+            b'a' => out.push(0x07),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'v' => out.push(0x0b),
+            // line continuation
+            b'\n' => (),
+            b'x' => {
+                let end = body[pos..]
+                    .iter()
+                    .position(|b| !b.is_ascii_hexdigit())
+                    .map_or(body.len(), |n| pos + n);
+                let digits = std::str::from_utf8(&body[pos..end]).unwrap_or_default();
+                out.push(u32::from_str_radix(digits, 16).unwrap_or(0) as u8);
+                pos = end;
+            }
+            b'0'..=b'7' => {
+                let mut value = (c - b'0') as u32;
+                let mut count = 1;
+                while count < 3 && pos < body.len() && (b'0'..=b'7').contains(&body[pos]) {
+                    value = value * 8 + (body[pos] - b'0') as u32;
+                    pos += 1;
+                    count += 1;
+                }
+                out.push(value as u8);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(src: &str) -> Vec<&str> {
+        tokenize(src).iter().map(|t| t.text).collect()
+    }
+
+    #[test]
+    fn non_ascii_comments() {
+        assert_eq!(
+            texts("int main() { /* café */ return 0; }"),
+            ["int", "main", "(", ")", "{", "return", "0", ";", "}"]
+        );
+        assert_eq!(texts("x; // naïve ✓\ny;"), ["x", ";", "y", ";"]);
+        assert_eq!(texts("/* ünterminated"), Vec::<&str>::new());
+        assert_eq!(texts("a /* é*/ b"), ["a", "b"]);
+    }
+
+    #[test]
+    fn non_ascii_strings() {
+        let tokens = tokenize("puts(\"héllo\"); char c = 'é'; x = ø;");
+        assert!(tokens[2].kind == TokenKind::Str);
+        assert_eq!(tokens[2].text, "\"héllo\"");
+        assert!(tokens[8].kind == TokenKind::Char);
+        assert_eq!(tokens[8].text, "'é'");
+        assert_eq!(tokens[12].text, "ø");
+        assert_eq!(unescape(tokens[2].text), "héllo".as_bytes());
+    }
+
+    #[test]
+    fn prefixed_literals() {
+        let tokens = tokenize("L\"wide\" u8\"utf8\" U'x' u'y' Lx");
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind).collect();
+        assert!(
+            kinds
+                == [
+                    TokenKind::Str,
+                    TokenKind::Str,
+                    TokenKind::Char,
+                    TokenKind::Char,
+                    TokenKind::Ident
+                ]
+        );
+        assert_eq!(unescape(tokens[1].text), b"utf8");
+    }
+
+    #[test]
+    fn line_continuations() {
+        let src = "#define MAX \\\n  16\nint a = \\\n 1;\n// comment \\\n still comment\nint b = \"x\\\ny\"; int c;";
+        let tokens = tokenize(src);
+        assert_eq!(
+            tokens.iter().map(|t| t.text).collect::<Vec<_>>(),
+            [
+                "int",
+                "a",
+                "=",
+                "1",
+                ";",
+                "int",
+                "b",
+                "=",
+                "\"x\\\ny\"",
+                ";",
+                "int",
+                "c",
+                ";"
+            ]
+        );
+        let at = |text: &str| {
+            let t = tokens.iter().rfind(|t| t.text == text).unwrap();
+            (t.line, t.column)
+        };
+        assert_eq!(at("a"), (3, 5));
+        assert_eq!(at("1"), (4, 2));
+        assert_eq!(at("b"), (7, 5));
+        assert_eq!(at("c"), (8, 9));
+        assert_eq!(unescape(tokens[8].text), b"xy");
+    }
+
+    #[test]
+    fn locations() {
+        let tokens = tokenize("a\n  /* é\n */ b");
+        assert_eq!((tokens[1].line, tokens[1].column), (3, 5));
+        assert_eq!(tokens[1].offset, "a\n  /* é\n */ ".len());
+    }
+}
@@ -0,0 +1,163 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of lines in a reported diff
+const MAX_DIFF_LINES: usize = 40;
+
+/// Maximum size of the table for aligning lines, beyond which lines are compared one by one
+const MAX_ALIGN_CELLS: usize = 1_000_000;
+
+/// How an output is compared against the expected one
+#[derive(Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareMode {
+    /// byte-for-byte
+    #[default]
+    Exact,
+    /// ignoring the amount of whitespace within lines and at the end of the output
+    Whitespace,
+    /// each expected line is a regular expression the output line must match as a whole
+    Regex,
+}
+
+impl CompareMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Whitespace => "whitespace",
+            Self::Regex => "regex",
+        }
+    }
+}
+
+/// Compare an output against the expected one, returning a diff if they do not match
+pub fn compare(mode: CompareMode, expected: &str, actual: &str) -> Option<String> {
+    match mode {
+        CompareMode::Exact => {
+            if expected == actual {
+                return None;
+            }
+            let (expected, actual) = (lines(expected), lines(actual));
+            let diff = diff_lines(&expected, &actual, |i, j| expected[i] == actual[j]);
+            Some(diff.unwrap_or_else(|| "outputs differ in the trailing newline".to_string()))
+        }
+        CompareMode::Whitespace => {
+            let normalize = |text: &str| -> Vec<String> {
+                let mut normalized: Vec<_> = text
+                    .lines()
+                    .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .collect();
+                while normalized.last().is_some_and(|l| l.is_empty()) {
+                    normalized.pop();
+                }
+                normalized
+            };
+            let expected = normalize(expected);
+            let actual = normalize(actual);
+            let expected: Vec<_> = expected.iter().map(|l| l.as_str()).collect();
+            let actual: Vec<_> = actual.iter().map(|l| l.as_str()).collect();
+            diff_lines(&expected, &actual, |i, j| expected[i] == actual[j])
+        }
+        CompareMode::Regex => {
+            let expected = lines(expected);
+            let mut patterns = vec![];
+            for (i, line) in expected.iter().enumerate() {
+                match Regex::new(&format!("^(?:{})$", line)) {
+                    Ok(pattern) => patterns.push(pattern),
+                    Err(err) => {
+                        return Some(format!("invalid pattern on line {}: {}", i + 1, err));
+                    }
+                }
+            }
+            let actual = lines(actual);
+            diff_lines(&expected, &actual, |i, j| patterns[i].is_match(actual[j]))
+        }
+    }
+}
+
+/// Lines of a text, without the line terminators
+fn lines(text: &str) -> Vec<&str> {
+    text.lines().collect()
+}
+
+/// Diff two sequences of lines given an equivalence on their indices, `None` if they are
+/// equivalent
+///
+/// Lines only in the expected side are prefixed with `-`, lines only in the actual side with
+/// `+`, and unchanged lines around the changes with a space.
+fn diff_lines<F>(expected: &[&str], actual: &[&str], eq: F) -> Option<String>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let (n, m) = (expected.len(), actual.len());
+
+    // edit script, as (tag, line) pairs
+    let mut script = vec![];
+    if n.saturating_mul(m) <= MAX_ALIGN_CELLS {
+        // longest common subsequence, from the back so the script is built front to back
+        let mut table = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                table[i][j] = if eq(i, j) {
+                    table[i + 1][j + 1] + 1
+                } else {
+                    table[i + 1][j].max(table[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && eq(i, j) {
+                script.push((' ', actual[j]));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || table[i + 1][j] >= table[i][j + 1]) {
+                script.push(('-', expected[i]));
+                i += 1;
+            } else {
+                script.push(('+', actual[j]));
+                j += 1;
+            }
+        }
+    } else {
+        for k in 0..n.max(m) {
+            match (expected.get(k), actual.get(k)) {
+                (Some(_), Some(a)) if eq(k, k) => script.push((' ', *a)),
+                (e, a) => {
+                    if let Some(e) = e {
+                        script.push(('-', *e));
+                    }
+                    if let Some(a) = a {
+                        script.push(('+', *a));
+                    }
+                }
+            }
+        }
+    }
+    if script.iter().all(|(tag, _)| *tag == ' ') {
+        return None;
+    }
+
+    // keep the changes with one line of context around them
+    let changed: Vec<_> = script.iter().map(|(tag, _)| *tag != ' ').collect();
+    let mut out = vec![];
+    let mut skipped = false;
+    for (k, (tag, line)) in script.iter().enumerate() {
+        let near_change =
+            changed[k] || (k > 0 && changed[k - 1]) || changed.get(k + 1).copied().unwrap_or(false);
+        if !near_change {
+            skipped = true;
+            continue;
+        }
+        if skipped && !out.is_empty() {
+            out.push("...".to_string());
+        }
+        skipped = false;
+        if out.len() >= MAX_DIFF_LINES {
+            out.push("... (diff truncated)".to_string());
+            break;
+        }
+        out.push(format!("{} {}", tag, line));
+    }
+    Some(out.join("\n"))
+}
@@ -132,15 +132,12 @@ impl Dock {
             ..Default::default()
         };
         for container in wait_for(self.docker.list_containers(Some(opts)))? {
-            match container.id {
-                None => (),
-                Some(id) => {
-                    if container
-                        .names
-                        .map_or(false, |names| names.into_iter().any(|n| n == name))
-                    {
-                        candidates.insert(id);
-                    }
+            if let Some(id) = container.id {
+                if container
+                    .names
+                    .is_some_and(|names| names.into_iter().any(|n| n == name))
+                {
+                    candidates.insert(id);
                 }
             }
         }
@@ -381,6 +378,7 @@ impl Dock {
         timeout: Option<Duration>,
         binding: BTreeMap<&Path, String>,
         workdir: Option<String>,
+        cpuset: Option<String>,
     ) -> Result<ExitStatus> {
         // check container existence
         let ephemeral_name = format!("{}-ephemeral-{}", tag, self.name);
@@ -426,6 +424,7 @@ impl Dock {
                         .map(|(h, c)| format!("{}:{}", h.to_str().unwrap(), c))
                         .collect(),
                 ),
+                cpuset_cpus: cpuset,
                 ..Default::default()
             }),
             ..Default::default()
@@ -534,6 +533,7 @@ impl Dock {
             None,
             binding,
             workdir,
+            None,
         )?;
 
         // done
@@ -552,8 +552,11 @@ impl Dock {
         timeout: Option<Duration>,
         binding: BTreeMap<&Path, String>,
         workdir: Option<String>,
+        cpuset: Option<String>,
     ) -> Result<ExitStatus> {
-        self._run(tag, None, cmd, net, tty, console, timeout, binding, workdir)
+        self._run(
+            tag, None, cmd, net, tty, console, timeout, binding, workdir, cpuset,
+        )
     }
 
     /// Invoke a simple command on a container in sandboxed environment and discard it
@@ -574,6 +577,35 @@ impl Dock {
             Some(timeout.unwrap_or(DEFAULT_SANDBOX_TIMEOUT)),
             binding,
             workdir,
+            None,
+        )
+    }
+
+    /// Same as `sandbox` but with the container pinned to the given CPU cores
+    pub fn sandbox_pinned(
+        &self,
+        tag: &str,
+        cmd: Vec<String>,
+        timeout: Option<Duration>,
+        binding: BTreeMap<&Path, String>,
+        workdir: Option<String>,
+        cores: &[usize],
+    ) -> Result<ExitStatus> {
+        let cpuset = cores
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.invoke(
+            tag,
+            cmd,
+            false,
+            true,
+            false,
+            Some(timeout.unwrap_or(DEFAULT_SANDBOX_TIMEOUT)),
+            binding,
+            workdir,
+            Some(cpuset),
         )
     }
 }